@@ -1,31 +1,290 @@
 /// Use Argon2 to create a password and check if it is valid.
+///
+/// New hashes use Argon2id with the cost parameters of the configuration.
+/// Hashes created with another variant or other parameters are still accepted
+/// but reported as outdated by [`password::verify`] so that they can be
+/// replaced on the next successful login.
 pub mod password {
-    use argon2::{self, Config};
+    use crate::config;
+    use argon2::{self, Config, ThreadMode, Variant, Version};
     use rand;
 
-    /// Hash a password with a random salt and default Argon2 configuration.
+    /// Outcome of a password verification.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Verification {
+        /// The password does not match.
+        Invalid,
+        /// The password matches and the hash is up to date.
+        Valid,
+        /// The password matches but should be hashed again.
+        Outdated,
+    }
+
+    impl Verification {
+        /// Does the password match the hash.
+        pub fn is_valid(&self) -> bool {
+            *self != Verification::Invalid
+        }
+    }
+
+    /// Argon2 configuration from the environment.
+    fn config<'a>() -> Config<'a> {
+        Config {
+            variant: Variant::Argon2id,
+            version: Version::Version13,
+            mem_cost: *config::ARGON2_MEMORY_COST,
+            time_cost: *config::ARGON2_TIME_COST,
+            lanes: *config::ARGON2_PARALLELISM,
+            thread_mode: ThreadMode::Sequential,
+            ..Config::default()
+        }
+    }
+
+    /// Hash a password with a random salt and the configured parameters.
     pub fn hash(password: &str) -> argon2::Result<String> {
-        let config = Config::default();
         let salt: [u8; 16] = rand::random();
-        argon2::hash_encoded(password.as_bytes(), &salt, &config)
+        argon2::hash_encoded(password.as_bytes(), &salt, &config())
+    }
+
+    /// Check if the encoded hash was created with the current configuration.
+    /// The encoded format is '$variant$v=version$m=mem,t=time,p=lanes$...'.
+    fn is_current(hash: &str) -> bool {
+        let config = config();
+        let parts: Vec<&str> = hash.split('$').collect();
+        if parts.len() != 6 {
+            return false;
+        }
+        let parameters = format!(
+            "m={},t={},p={}",
+            config.mem_cost, config.time_cost, config.lanes
+        );
+        parts[1] == config.variant.as_lowercase_str()
+            && parts[2] == format!("v={}", config.version.as_u32())
+            && parts[3] == parameters
     }
 
     /// Check if the password matches against a given hash.
-    pub fn verify(password: &str, hash: &str) -> bool {
-        argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+    pub fn verify(password: &str, hash: &str) -> Verification {
+        match argon2::verify_encoded(hash, password.as_bytes()) {
+            Ok(true) if is_current(hash) => Verification::Valid,
+            Ok(true) => Verification::Outdated,
+            _ => Verification::Invalid,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::env;
+        use std::sync::Once;
+
+        // Hash of 'Trustno1!' used by the seeded users of db/populate.sql.
+        const SEEDED_HASH: &str = "$argon2i$v=19$m=4096,t=3,p=1$jYwfWYO26anwMuRnN1FdHA$TDUUjIe62D7Uq/x+jxBdpPxBeeNiLD8pFmSh2o++1Iw";
+
+        static CONFIG: Once = Once::new();
+
+        /// Cheap parameters, set before the configuration is first read.
+        fn configure() {
+            CONFIG.call_once(|| {
+                env::set_var("ARGON2_MEMORY_COST", "1024");
+                env::set_var("ARGON2_TIME_COST", "1");
+                env::set_var("ARGON2_PARALLELISM", "1");
+            });
+        }
+
+        #[test]
+        fn seeded_hash_is_outdated() {
+            configure();
+            assert!(!is_current(SEEDED_HASH));
+            assert_eq!(
+                verify("Trustno1!", SEEDED_HASH),
+                Verification::Outdated
+            );
+            assert_eq!(verify("Trustno2!", SEEDED_HASH), Verification::Invalid);
+        }
+
+        #[test]
+        fn new_hash_is_current() {
+            configure();
+            let hash = hash("Trustno1!").unwrap();
+            assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+            assert!(is_current(&hash));
+            assert_eq!(verify("Trustno1!", &hash), Verification::Valid);
+            assert_eq!(verify("Trustno2!", &hash), Verification::Invalid);
+        }
+
+        #[test]
+        fn other_parameters_are_outdated() {
+            configure();
+            let salt = [0; 16];
+            let other = Config {
+                time_cost: 2,
+                ..config()
+            };
+            let hash =
+                argon2::hash_encoded(b"Trustno1!", &salt, &other).unwrap();
+            assert!(!is_current(&hash));
+            assert_eq!(verify("Trustno1!", &hash), Verification::Outdated);
+            assert!(!is_current("not a hash"));
+        }
+    }
+}
+
+/// Time-based one-time passwords (RFC 6238) for two-factor authentication.
+///
+/// The functions take the current unix time as a parameter instead of reading
+/// the system clock.
+pub mod totp {
+    use data_encoding::BASE32_NOPAD;
+    use hmac::{Hmac, Mac};
+    use rand::{self, distributions::Alphanumeric, Rng};
+    use sha1::Sha1;
+
+    /// Validity period of a code in seconds.
+    pub const PERIOD: u64 = 30;
+
+    /// Number of digits of a code.
+    pub const DIGITS: usize = 6;
+
+    /// Number of periods before and after the current one during which a code
+    /// is still accepted to allow for clock drift.
+    const SKEW: u64 = 1;
+
+    /// Number of recovery codes generated on enrollment.
+    const RECOVERY_CODE_COUNT: usize = 10;
+
+    /// Generate a new random base32 encoded secret.
+    pub fn secret() -> String {
+        let bytes: [u8; 20] = rand::random();
+        BASE32_NOPAD.encode(&bytes)
+    }
+
+    /// Provisioning uri to be given to the authenticator application.
+    pub fn uri(secret: &str, issuer: &str, account: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            issuer, account, secret, issuer, DIGITS, PERIOD
+        )
+    }
+
+    /// Compute the code of the given time step (HOTP, RFC 4226).
+    fn step_code(key: &[u8], step: u64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(key)
+            .expect("HMAC can take a key of any size");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+        let code = binary % 10u32.pow(DIGITS as u32);
+        format!("{:0width$}", code, width = DIGITS)
+    }
+
+    /// Code of the given secret at the given unix time.
+    pub fn code(secret: &str, time: u64) -> Option<String> {
+        let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+        Some(step_code(&key, time / PERIOD))
+    }
+
+    /// Check the code against the secret at the given unix time. Returns the
+    /// time step of the code so that it can only be used once.
+    pub fn verify(secret: &str, given: &str, time: u64) -> Option<u64> {
+        let step = time / PERIOD;
+        (step.saturating_sub(SKEW)..=step + SKEW).find(|step| {
+            code(secret, step * PERIOD).as_deref() == Some(given.trim())
+        })
+    }
+
+    /// Generate a new set of one-time recovery codes.
+    pub fn recovery_codes() -> Vec<String> {
+        (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let code: String = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(10)
+                    .map(|c| char::from(c).to_ascii_lowercase())
+                    .collect();
+                format!("{}-{}", &code[..5], &code[5..])
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // Base32 encoding of the RFC 6238 SHA-1 secret "12345678901234567890".
+        const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+        // RFC 6238 SHA-1 test vectors truncated to six digits.
+        const VECTORS: [(u64, &str); 6] = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+
+        #[test]
+        fn code_matches_rfc_vectors() {
+            for (time, expected) in VECTORS {
+                assert_eq!(code(SECRET, time).as_deref(), Some(expected));
+            }
+        }
+
+        #[test]
+        fn verify_returns_the_step_of_rfc_vectors() {
+            for (time, expected) in VECTORS {
+                assert_eq!(verify(SECRET, expected, time), Some(time / PERIOD));
+            }
+        }
+
+        #[test]
+        fn verify_accepts_one_step_of_skew() {
+            // "081804" is the code of the step from 1111111080 to 1111111109
+            let step = Some(1111111109 / PERIOD);
+            assert_eq!(verify(SECRET, "081804", 1111111049), None);
+            assert_eq!(verify(SECRET, "081804", 1111111050), step);
+            assert_eq!(verify(SECRET, "081804", 1111111080), step);
+            assert_eq!(verify(SECRET, "081804", 1111111139), step);
+            assert_eq!(verify(SECRET, "081804", 1111111140), None);
+        }
+
+        #[test]
+        fn verify_rejects_invalid_codes() {
+            assert_eq!(verify(SECRET, "000000", 59), None);
+            assert_eq!(verify(SECRET, "", 59), None);
+            assert_eq!(verify("not base32!", "287082", 59), None);
+            assert_eq!(verify(SECRET, " 287082 ", 59), Some(1));
+        }
     }
 }
 
 /// Manage user sessions with cookies.
 pub mod session {
-    use crate::cache::Cache;
+    use crate::cache::{self, Cache};
+    use crate::config;
     use crate::query::{self, PostgresDb};
-    use crate::uuid::{SerdeUuid, SqlxUuid};
+    use crate::uuid::{from_serde_to_sqlx, SerdeUuid, SqlxUuid};
+    use rocket::http::{Cookie, CookieJar, Status};
+    use rocket::outcome::try_outcome;
     use rocket::request::{FromRequest, Outcome, Request};
     use rocket::serde::{json, Deserialize, Serialize};
-    use rocket::{http::Status, State};
+    use rocket::time::OffsetDateTime;
+    use rocket::State;
     use rocket_db_pools::Connection;
+    use std::convert::Infallible;
     use std::fmt;
+    use std::time::Duration;
+
+    // Minimum time between two updates of the 'last_seen' field in seconds.
+    // This avoids writing in the session cache on every request.
+    const LAST_SEEN_PRECISION: i64 = 60;
 
     /// Check if the user account exists.
     async fn is_valid_account(
@@ -56,6 +315,90 @@ pub mod session {
     /// The user may or may not be logged in to use the given route.
     pub struct IsConnected(pub Option<Connected>);
 
+    /// The user must be logged in with an admin account to use the given
+    /// route.
+    pub struct Admin(pub Connected);
+
+    /// Client information attached to a session. This is always available as
+    /// a request guard.
+    pub struct Device {
+        /// user-agent header of the client
+        pub user_agent: Option<String>,
+        /// ip address of the client
+        pub ip: Option<String>,
+    }
+
+    /// An active session as stored in the session cache.
+    #[derive(Serialize, Deserialize)]
+    #[serde(crate = "rocket::serde")]
+    pub struct Active {
+        /// session sent to the user
+        pub connected: Connected,
+        /// login timestamp
+        pub creation_ts: i64,
+        /// timestamp of the last authenticated request
+        pub last_seen: i64,
+        /// timestamp at which the session expires
+        pub expiration_ts: i64,
+        /// inactivity time in seconds after which the session expires
+        pub duration: u64,
+        /// user-agent header of the client at login
+        pub user_agent: Option<String>,
+        /// ip address of the client at login
+        pub ip: Option<String>,
+    }
+
+    impl Active {
+        /// Create a new active session lasting for the given duration of
+        /// inactivity.
+        pub fn new(
+            connected: &Connected,
+            device: &Device,
+            duration: Duration,
+        ) -> Self {
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            Active {
+                connected: connected.clone(),
+                creation_ts: now,
+                last_seen: now,
+                expiration_ts: now + duration.as_secs() as i64,
+                duration: duration.as_secs(),
+                user_agent: device.user_agent.clone(),
+                ip: device.ip.clone(),
+            }
+        }
+
+        /// Record activity on the session and push back its expiration.
+        pub fn refresh(&mut self) {
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            self.last_seen = now;
+            self.expiration_ts = now + self.duration as i64;
+        }
+
+        /// Expiration date of the session.
+        pub fn expires(&self) -> OffsetDateTime {
+            OffsetDateTime::from_unix_timestamp(self.expiration_ts)
+                .unwrap_or_else(|_| OffsetDateTime::now_utc())
+        }
+
+        /// Time left before expiration.
+        pub fn lifetime(&self) -> Duration {
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            Duration::from_secs((self.expiration_ts - now).max(0) as u64)
+        }
+
+        /// Store the session in the cache.
+        pub async fn save(
+            &self,
+            sessions: &Cache<Active>,
+        ) -> Result<(), cache::Error> {
+            sessions
+                .set(&self.connected.key(), self, self.lifetime())
+                .await?;
+            Ok(())
+        }
+    }
+
     impl Connected {
         /// Create a new connected session for the given user
         pub fn new(account_id: SerdeUuid, username: &str) -> Self {
@@ -74,20 +417,36 @@ pub mod session {
             }
         }
 
-        /// Check that the stored session matches the given cookie and that the
-        /// user actually exists.
-        pub async fn is_valid(
+        /// Key prefix of every session of the given account in the cache.
+        pub fn account_prefix(account_id: &SerdeUuid) -> String {
+            format!("{}:", account_id)
+        }
+
+        /// Key of the session in the cache.
+        pub fn key(&self) -> String {
+            format!(
+                "{}{}",
+                Connected::account_prefix(&self.account_id),
+                self.session_id
+            )
+        }
+
+        /// Get the stored session matching the given cookie if the user
+        /// actually exists.
+        pub async fn active(
             &self,
-            sessions: &Cache<Connected>,
+            sessions: &Cache<Active>,
             db: &mut Connection<PostgresDb>,
-        ) -> bool {
+        ) -> Result<Option<Active>, cache::Error> {
             let account_id = self.account_id.to_string();
-            match sessions.get(&account_id) {
-                Some(stored_session) => {
-                    stored_session.session_id == self.session_id
-                        && is_valid_account(&account_id, db).await
+            match sessions.get(&self.key()).await? {
+                Some(active)
+                    if active.connected.account_id == self.account_id
+                        && is_valid_account(&account_id, db).await =>
+                {
+                    Ok(Some(active))
                 }
-                None => false,
+                _ => Ok(None),
             }
         }
     }
@@ -100,11 +459,82 @@ pub mod session {
         }
     }
 
+    /// Add the session cookies. The session is sent in an encrypted and
+    /// http-only cookie. A second cookie, readable by the front, only contains
+    /// the username for display purposes and is never trusted by the api.
+    pub fn add_cookies(cookies: &CookieJar<'_>, active: &Active) {
+        let session = &active.connected;
+        let expires = active.expires();
+        let session_cookie = Cookie::build("session", session.to_string())
+            .http_only(true)
+            .secure(*config::COOKIE_SECURE)
+            .same_site(*config::COOKIE_SAME_SITE)
+            .expires(expires)
+            .finish();
+        cookies.add_private(session_cookie);
+
+        let user = json::json!({ "username": session.username });
+        let user_cookie = Cookie::build("user", user.to_string())
+            .http_only(false)
+            .secure(*config::COOKIE_SECURE)
+            .same_site(*config::COOKIE_SAME_SITE)
+            .expires(expires)
+            .finish();
+        cookies.add(user_cookie);
+    }
+
+    /// Remove the session cookies.
+    pub fn remove_cookies(cookies: &CookieJar<'_>) {
+        cookies.remove_private(Cookie::named("session"));
+        cookies.remove(Cookie::named("user"));
+    }
+
+    /// Authenticate the request from its session cookie and record the
+    /// activity on the stored session. This slides the expiration of the
+    /// session and of its cookies. Cookies that cannot be decrypted have been
+    /// tampered with and are rejected.
+    async fn authenticate(
+        request: &Request<'_>,
+    ) -> Result<Option<Connected>, cache::Error> {
+        let cookie = match request.cookies().get_private("session") {
+            Some(cookie) => cookie,
+            None => return Ok(None),
+        };
+        let mut db = request
+            .guard::<Connection<PostgresDb>>()
+            .await
+            .expect("Failed to get database connection");
+        let sessions = request
+            .guard::<&State<Cache<Active>>>()
+            .await
+            .expect("Failed to get connected session cache");
+        let session = match Connected::from_str(cookie.value()) {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+        let mut active = match session.active(sessions, &mut db).await? {
+            Some(active) => active,
+            None => return Ok(None),
+        };
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        if now - active.last_seen >= LAST_SEEN_PRECISION {
+            active.refresh();
+            // the stored session is still valid if it could not be refreshed
+            match active.save(sessions).await {
+                Ok(()) => add_cookies(request.cookies(), &active),
+                Err(error) => error!("failed to refresh session: {}", error),
+            }
+        }
+        Ok(Some(active.connected))
+    }
+
     #[derive(Debug)]
     pub enum Error {
         LoggedIn,
         NotLoggedIn,
         InvalidSession,
+        NotAdmin,
+        Storage,
     }
 
     #[rocket::async_trait]
@@ -114,7 +544,7 @@ pub mod session {
         async fn from_request(
             request: &'r Request<'_>,
         ) -> Outcome<Self, Self::Error> {
-            match request.cookies().get("session") {
+            match request.cookies().get_private("session") {
                 None => Outcome::Success(Unconnected {}),
                 Some(_session_cookie) => {
                     Outcome::Failure((Status::Forbidden, Error::LoggedIn))
@@ -130,59 +560,87 @@ pub mod session {
         async fn from_request(
             request: &'r Request<'_>,
         ) -> Outcome<Self, Self::Error> {
-            let mut db = request
-                .guard::<Connection<PostgresDb>>()
-                .await
-                .expect("Failed to get database connection");
-            let sessions = request
-                .guard::<&State<Cache<Connected>>>()
-                .await
-                .expect("Failed to get connected session cache");
             match request.cookies().get("session") {
                 None => {
                     Outcome::Failure((Status::Unauthorized, Error::NotLoggedIn))
                 }
-                Some(cookie) => match Connected::from_str(cookie.value()) {
-                    Some(session)
-                        if session.is_valid(sessions, &mut db).await =>
-                    {
-                        Outcome::Success(session)
-                    }
-                    _ => Outcome::Failure((
+                Some(_) => match authenticate(request).await {
+                    Ok(Some(session)) => Outcome::Success(session),
+                    Ok(None) => Outcome::Failure((
                         Status::BadRequest,
                         Error::InvalidSession,
                     )),
+                    Err(error) => {
+                        error!("failed to authenticate: {}", error);
+                        Outcome::Failure((
+                            Status::InternalServerError,
+                            Error::Storage,
+                        ))
+                    }
                 },
             }
         }
     }
 
     #[rocket::async_trait]
-    impl<'r> FromRequest<'r> for IsConnected {
+    impl<'r> FromRequest<'r> for Admin {
         type Error = Error;
 
         async fn from_request(
             request: &'r Request<'_>,
         ) -> Outcome<Self, Self::Error> {
+            let connected = try_outcome!(request.guard::<Connected>().await);
             let mut db = request
                 .guard::<Connection<PostgresDb>>()
                 .await
                 .expect("Failed to get database connection");
-            let sessions = request
-                .guard::<&State<Cache<Connected>>>()
-                .await
-                .expect("Failed to get connected session cache");
+            let account_id = from_serde_to_sqlx(&connected.account_id);
+            match query::get_user_by_account_id(&account_id, &mut db).await {
+                Some(account) if account.admin => {
+                    Outcome::Success(Admin(connected))
+                }
+                _ => Outcome::Failure((Status::Forbidden, Error::NotAdmin)),
+            }
+        }
+    }
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for IsConnected {
+        type Error = Error;
+
+        async fn from_request(
+            request: &'r Request<'_>,
+        ) -> Outcome<Self, Self::Error> {
             match request.cookies().get("session") {
                 None => Outcome::Success(IsConnected { 0: None }),
-                Some(cookie) => match Connected::from_str(cookie.value()) {
-                    Some(session)
-                        if session.is_valid(sessions, &mut db).await =>
-                    {
-                        Outcome::Success(IsConnected { 0: Some(session) })
+                Some(_) => match authenticate(request).await {
+                    Ok(session) => Outcome::Success(IsConnected { 0: session }),
+                    Err(error) => {
+                        error!("failed to authenticate: {}", error);
+                        Outcome::Failure((
+                            Status::InternalServerError,
+                            Error::Storage,
+                        ))
                     }
-                    _ => Outcome::Success(IsConnected { 0: None }),
                 },
             }
         }
     }
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for Device {
+        type Error = Infallible;
+
+        async fn from_request(
+            request: &'r Request<'_>,
+        ) -> Outcome<Self, Self::Error> {
+            Outcome::Success(Device {
+                user_agent: request
+                    .headers()
+                    .get_one("User-Agent")
+                    .map(String::from),
+                ip: request.client_ip().map(|ip| ip.to_string()),
+            })
+        }
+    }
 }
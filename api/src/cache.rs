@@ -5,27 +5,110 @@
 //! a rocket `State` by the routes' handlers. It is key-value pair system with
 //! setter, getter and remove functions.
 //!
+//! The values are serialized and kept in a [`Storage`] backend shared by every
+//! Cache. Each Cache has its own namespace in the storage so that keys from
+//! different caches never collide. The backend is selected with the
+//! `CACHE_STORAGE` configuration variable: `memory` keeps everything in the api
+//! process while `postgres` persists the values in the database so that they
+//! survive restarts and can be shared by multiple api instances. Storage
+//! failures are returned to the caller so that a lost value is never reported
+//! as a success.
+//!
 //! When creating a Cache for a given type and managing it with rocket, do not
 //! forget to add it in the cleanup job of the `main` module. Otherwise the
 //! expired values would not necessarily be deleted which could cause a memory
 //! leak.
 
+use crate::query;
+use rocket::serde::{de::DeserializeOwned, json, Serialize};
+use rocket_db_pools::sqlx;
+use rocket_db_pools::sqlx::PgPool;
 use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use strum::EnumString;
+
+/// Available storage backends.
+#[derive(Debug, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Backend {
+    Memory,
+    Postgres,
+}
+
+/// Failure of the storage backend. The value could not be stored, read or
+/// deleted.
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cache storage error: {}", self.0)
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(error: sqlx::Error) -> Self {
+        Error(error.to_string())
+    }
+}
+
+/// Storage backend of the caches. Values are stored as serialized strings
+/// under a namespace and a key.
+#[rocket::async_trait]
+pub trait Storage: Send + Sync {
+    /// Set a value for the given key. Returns the old value if it was still
+    /// valid.
+    async fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: String,
+        lifetime: Duration,
+    ) -> Result<Option<String>, Error>;
+
+    /// Get a value if it exists and is not expired.
+    async fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, Error>;
+
+    /// Delete a value. Returns it if it was still valid.
+    async fn del(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, Error>;
+
+    /// Check if a given key exists.
+    async fn exists(&self, namespace: &str, key: &str) -> Result<bool, Error>;
+
+    /// List every valid value whose key starts with the given prefix.
+    async fn list(
+        &self,
+        namespace: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>, Error>;
+
+    /// Remove every expired value from the namespace.
+    async fn cleanup(&self, namespace: &str) -> Result<(), Error>;
+}
 
 /// Cache item. Stores a value for a certain time.
-struct CacheItem<T: Clone> {
-    value: T,
+struct CacheItem {
+    value: String,
     creation: Instant,
     lifetime: Duration,
 }
 
-impl<T: Clone> CacheItem<T> {
+impl CacheItem {
     /// Create a new cache item.
-    fn new(value: &T, lifetime: Duration) -> Self {
+    fn new(value: String, lifetime: Duration) -> Self {
         CacheItem {
-            value: value.clone(),
+            value,
             creation: Instant::now(),
             lifetime,
         }
@@ -35,84 +118,253 @@ impl<T: Clone> CacheItem<T> {
     fn is_expired(&self) -> bool {
         self.creation.elapsed() > self.lifetime
     }
+
+    /// Return the value if the item is still valid.
+    fn into_valid(self) -> Option<String> {
+        if self.is_expired() {
+            None
+        } else {
+            Some(self.value)
+        }
+    }
 }
 
-/// Cache. Thread safe local cache system. This is an equivalent of Redis but
-/// without Redis.
-#[derive(Clone)]
-pub struct Cache<T: Clone> {
-    safe: Arc<Mutex<HashMap<String, CacheItem<T>>>>,
+/// Thread safe local storage. This is an equivalent of Redis but without
+/// Redis. Everything is lost when the api stops.
+pub struct MemoryStorage {
+    safe: Mutex<HashMap<(String, String), CacheItem>>,
 }
 
-impl<T: Clone> Cache<T> {
-    /// Create a new instance of the Cache structure.
+impl MemoryStorage {
+    /// Create a new empty memory storage.
     pub fn new() -> Self {
-        Cache {
-            safe: Arc::new(Mutex::new(HashMap::new())),
+        MemoryStorage {
+            safe: Mutex::new(HashMap::new()),
         }
     }
+}
 
-    /// Set a key-value pair in the cache. Returns the old value if a value
-    /// already existed for the given key. The lifetime is the duration of
-    /// storage inside the Cache. Passed it the data will be deleted.
-    pub fn set(&self, key: &str, value: &T, lifetime: Duration) -> Option<T> {
+#[rocket::async_trait]
+impl Storage for MemoryStorage {
+    async fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: String,
+        lifetime: Duration,
+    ) -> Result<Option<String>, Error> {
+        let mut map = self.safe.lock().expect("Could not lock the cache.");
+        Ok(map
+            .insert(
+                (namespace.to_string(), key.to_string()),
+                CacheItem::new(value, lifetime),
+            )
+            .and_then(CacheItem::into_valid))
+    }
+
+    async fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, Error> {
         let mut map = self.safe.lock().expect("Could not lock the cache.");
-        let old_item =
-            map.insert(key.to_string(), CacheItem::new(value, lifetime));
-        match old_item {
+        let map_key = (namespace.to_string(), key.to_string());
+        match map.get(&map_key) {
             Some(item) => {
                 if item.is_expired() {
-                    None
+                    map.remove(&map_key);
+                    Ok(None)
                 } else {
-                    Some(item.value)
+                    Ok(Some(item.value.clone()))
                 }
             }
-            None => None,
+            None => Ok(None),
         }
     }
 
-    /// Get a value from the Cache.
-    pub fn get(&self, key: &str) -> Option<T> {
+    async fn del(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, Error> {
         let mut map = self.safe.lock().expect("Could not lock the cache.");
-        match map.get(key) {
-            Some(item) => {
-                if item.is_expired() {
-                    map.remove(key);
-                    None
-                } else {
-                    Some(item.value.clone())
-                }
-            }
-            None => None,
+        Ok(map
+            .remove(&(namespace.to_string(), key.to_string()))
+            .and_then(CacheItem::into_valid))
+    }
+
+    async fn exists(&self, namespace: &str, key: &str) -> Result<bool, Error> {
+        let map = self.safe.lock().expect("Could not lock the cache.");
+        Ok(map.contains_key(&(namespace.to_string(), key.to_string())))
+    }
+
+    async fn list(
+        &self,
+        namespace: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>, Error> {
+        let map = self.safe.lock().expect("Could not lock the cache.");
+        Ok(map
+            .iter()
+            .filter(|((item_namespace, key), item)| {
+                item_namespace == namespace
+                    && key.starts_with(prefix)
+                    && !item.is_expired()
+            })
+            .map(|(_, item)| item.value.clone())
+            .collect())
+    }
+
+    async fn cleanup(&self, namespace: &str) -> Result<(), Error> {
+        let mut map = self.safe.lock().expect("Could not lock the cache.");
+        map.retain(|(item_namespace, _), item| {
+            item_namespace != namespace || !item.is_expired()
+        });
+        Ok(())
+    }
+}
+
+/// Persistent storage in the 'cache' table of the database. Expired rows are
+/// ignored by the getters and deleted by the cleanup job.
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    /// Create a new postgres storage from the database pool.
+    pub fn new(pool: PgPool) -> Self {
+        PostgresStorage { pool }
+    }
+}
+
+#[rocket::async_trait]
+impl Storage for PostgresStorage {
+    async fn set(
+        &self,
+        namespace: &str,
+        key: &str,
+        value: String,
+        lifetime: Duration,
+    ) -> Result<Option<String>, Error> {
+        Ok(
+            query::cache::set(&self.pool, namespace, key, &value, lifetime)
+                .await?,
+        )
+    }
+
+    async fn get(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, Error> {
+        Ok(query::cache::get(&self.pool, namespace, key).await?)
+    }
+
+    async fn del(
+        &self,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, Error> {
+        Ok(query::cache::del(&self.pool, namespace, key).await?)
+    }
+
+    async fn exists(&self, namespace: &str, key: &str) -> Result<bool, Error> {
+        Ok(query::cache::exists(&self.pool, namespace, key).await?)
+    }
+
+    async fn list(
+        &self,
+        namespace: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>, Error> {
+        Ok(query::cache::list(&self.pool, namespace, prefix).await?)
+    }
+
+    async fn cleanup(&self, namespace: &str) -> Result<(), Error> {
+        Ok(query::cache::cleanup(&self.pool, namespace).await?)
+    }
+}
+
+/// Cache. Typed handle on a namespace of the shared storage.
+pub struct Cache<T> {
+    namespace: &'static str,
+    storage: Arc<dyn Storage>,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Cache<T> {
+    fn clone(&self) -> Self {
+        Cache {
+            namespace: self.namespace,
+            storage: self.storage.clone(),
+            value_type: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Cache<T> {
+    /// Create a new instance of the Cache structure. The namespace must be
+    /// unique among the caches using the same storage.
+    pub fn new(namespace: &'static str, storage: &Arc<dyn Storage>) -> Self {
+        Cache {
+            namespace,
+            storage: storage.clone(),
+            value_type: PhantomData,
         }
     }
 
+    /// Deserialize a stored value.
+    fn decode(value: Option<String>) -> Option<T> {
+        value.and_then(|value| json::from_str(&value).ok())
+    }
+
+    /// Set a key-value pair in the cache. Returns the old value if a value
+    /// already existed for the given key. The lifetime is the duration of
+    /// storage inside the Cache. Passed it the data will be deleted.
+    pub async fn set(
+        &self,
+        key: &str,
+        value: &T,
+        lifetime: Duration,
+    ) -> Result<Option<T>, Error> {
+        let value = json::to_string(value).expect("Failed to serialize");
+        Ok(Self::decode(
+            self.storage
+                .set(self.namespace, key, value, lifetime)
+                .await?,
+        ))
+    }
+
+    /// Get a value from the Cache.
+    pub async fn get(&self, key: &str) -> Result<Option<T>, Error> {
+        Ok(Self::decode(self.storage.get(self.namespace, key).await?))
+    }
+
     /// Delete a value from the Cache. Returns the old value if it was still
     /// valid. So this method can basically be used as a 'pop' function.
-    pub fn del(&self, key: &str) -> Option<T> {
-        let mut map = self.safe.lock().expect("Could not lock the cache.");
-        match map.remove(key) {
-            Some(item) => {
-                if item.is_expired() {
-                    None
-                } else {
-                    Some(item.value)
-                }
-            }
-            None => None,
-        }
+    pub async fn del(&self, key: &str) -> Result<Option<T>, Error> {
+        Ok(Self::decode(self.storage.del(self.namespace, key).await?))
     }
 
     /// Check if a given key exists in the cache.
     #[allow(unused)]
-    pub fn exists(&self, key: &str) -> bool {
-        let map = self.safe.lock().expect("Could not lock the cache.");
-        map.contains_key(key)
+    pub async fn exists(&self, key: &str) -> Result<bool, Error> {
+        self.storage.exists(self.namespace, key).await
+    }
+
+    /// List every value whose key starts with the given prefix.
+    pub async fn list(&self, prefix: &str) -> Result<Vec<T>, Error> {
+        Ok(self
+            .storage
+            .list(self.namespace, prefix)
+            .await?
+            .into_iter()
+            .filter_map(|value| json::from_str(&value).ok())
+            .collect())
     }
 
     /// Cleanup the cache by removing expired items.
-    pub fn cleanup(&self) {
-        let mut map = self.safe.lock().expect("Could not lock the cache.");
-        map.retain(|_, item| !item.is_expired());
+    pub async fn cleanup(&self) -> Result<(), Error> {
+        self.storage.cleanup(self.namespace).await
     }
 }
@@ -1,6 +1,8 @@
 //! Configuration module loading environment variables
 
+use crate::cache;
 use lazy_static::lazy_static;
+use rocket::http::SameSite;
 use std::env;
 
 lazy_static! {
@@ -10,6 +12,12 @@ lazy_static! {
         .parse::<u64>()
         .expect("CACHE_CLEANUP_INTERVAL must be a number");
 
+    /// Storage backend of the caches ('memory' or 'postgres')
+    pub static ref CACHE_STORAGE: cache::Backend = env::var("CACHE_STORAGE")
+        .expect("missing CACHE_STORAGE env var")
+        .parse::<cache::Backend>()
+        .expect("CACHE_STORAGE must be either 'memory' or 'postgres'");
+
     /// Pictures directory
     pub static ref PICTURES_DIR: String = env::var("PICTURES_DIR")
         .expect("missing PICTURES_DIR env var");
@@ -20,6 +28,30 @@ lazy_static! {
         .parse::<usize>()
         .expect("PICTURES_SIZEMAX must be a number");
 
+    /// Quality of the saved pictures from 1 to 100
+    pub static ref PICTURES_QUALITY: u8 = match env::var("PICTURES_QUALITY")
+        .expect("missing PICTURES_QUALITY env var")
+        .parse::<u8>()
+        .expect("PICTURES_QUALITY must be a number")
+    {
+        quality @ 1..=100 => quality,
+        _ => panic!("PICTURES_QUALITY must be between 1 and 100"),
+    };
+
+    /// Command converting a HEIC picture to PNG, called with the input and
+    /// output paths
+    pub static ref HEIC_CONVERTER: String = env::var("HEIC_CONVERTER")
+        .expect("missing HEIC_CONVERTER env var");
+
+    /// Command converting a WebP picture to PNG, called with the input path
+    /// and the output path after a '-o' option
+    pub static ref WEBP_CONVERTER: String = env::var("WEBP_CONVERTER")
+        .expect("missing WEBP_CONVERTER env var");
+
+    /// File of the words forbidden in comments, one per line
+    pub static ref BANNED_WORDS_FILE: String = env::var("BANNED_WORDS_FILE")
+        .expect("missing BANNED_WORDS_FILE env var");
+
     /// Superposables directory
     pub static ref SUPERPOSABLES_DIR: String = env::var("SUPERPOSABLES_DIR")
         .expect("missing SUPERPOSABLES_DIR env var");
@@ -48,6 +80,71 @@ lazy_static! {
     pub static ref SMTP_PASSWORD: String = env::var("SMTP_PASSWORD")
         .expect("missing SMTP_PASSWORD env var");
 
+    /// Inactivity time in seconds after which a session expires
+    pub static ref SESSION_DURATION: u64 = env::var("SESSION_DURATION")
+        .expect("missing SESSION_DURATION env var")
+        .parse::<u64>()
+        .expect("SESSION_DURATION must be a number");
+
+    /// Session duration in seconds when logging in with the remember me option
+    pub static ref REMEMBER_ME_DURATION: u64 = env::var("REMEMBER_ME_DURATION")
+        .expect("missing REMEMBER_ME_DURATION env var")
+        .parse::<u64>()
+        .expect("REMEMBER_ME_DURATION must be a number");
+
+    /// Maximum number of attempts on rate limited routes within the window
+    pub static ref RATE_LIMIT_ATTEMPTS: u32 = env::var("RATE_LIMIT_ATTEMPTS")
+        .expect("missing RATE_LIMIT_ATTEMPTS env var")
+        .parse::<u32>()
+        .expect("RATE_LIMIT_ATTEMPTS must be a number");
+
+    /// Window in seconds during which the rate limited attempts are counted
+    pub static ref RATE_LIMIT_WINDOW: u64 = env::var("RATE_LIMIT_WINDOW")
+        .expect("missing RATE_LIMIT_WINDOW env var")
+        .parse::<u64>()
+        .expect("RATE_LIMIT_WINDOW must be a number");
+
+    /// Lockout duration in seconds when too many attempts have been made
+    pub static ref RATE_LIMIT_LOCKOUT: u64 = env::var("RATE_LIMIT_LOCKOUT")
+        .expect("missing RATE_LIMIT_LOCKOUT env var")
+        .parse::<u64>()
+        .expect("RATE_LIMIT_LOCKOUT must be a number");
+
+    /// Argon2id memory cost of the password hashes in kibibytes
+    pub static ref ARGON2_MEMORY_COST: u32 = env::var("ARGON2_MEMORY_COST")
+        .expect("missing ARGON2_MEMORY_COST env var")
+        .parse::<u32>()
+        .expect("ARGON2_MEMORY_COST must be a number");
+
+    /// Argon2id number of iterations of the password hashes
+    pub static ref ARGON2_TIME_COST: u32 = env::var("ARGON2_TIME_COST")
+        .expect("missing ARGON2_TIME_COST env var")
+        .parse::<u32>()
+        .expect("ARGON2_TIME_COST must be a number");
+
+    /// Argon2id degree of parallelism of the password hashes
+    pub static ref ARGON2_PARALLELISM: u32 = env::var("ARGON2_PARALLELISM")
+        .expect("missing ARGON2_PARALLELISM env var")
+        .parse::<u32>()
+        .expect("ARGON2_PARALLELISM must be a number");
+
+    /// Send the cookies only through https
+    pub static ref COOKIE_SECURE: bool = env::var("COOKIE_SECURE")
+        .expect("missing COOKIE_SECURE env var")
+        .parse::<bool>()
+        .expect("COOKIE_SECURE must be a boolean");
+
+    /// SameSite attribute of the cookies ('strict', 'lax' or 'none')
+    pub static ref COOKIE_SAME_SITE: SameSite = match env::var("COOKIE_SAME_SITE")
+        .expect("missing COOKIE_SAME_SITE env var")
+        .as_str()
+    {
+        "strict" => SameSite::Strict,
+        "lax" => SameSite::Lax,
+        "none" => SameSite::None,
+        _ => panic!("COOKIE_SAME_SITE must be 'strict', 'lax' or 'none'"),
+    };
+
     /// Front link
     pub static ref FRONT_LINK: String = env::var("FRONT_LINK")
         .expect("missing FRONT_LINK env var");
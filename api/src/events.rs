@@ -0,0 +1,69 @@
+//! Real-time events pushed to the clients.
+//!
+//! The picture routes publish their events on an in-process broadcast channel
+//! managed by rocket as a `State`. Every client connected to the `/events`
+//! stream gets its own receiver. Events are not stored: clients only get the
+//! events published while they are connected, and a client too slow to keep
+//! up with the channel skips the oldest ones.
+
+use crate::payload::{Comment, LikeCount, Picture};
+use rocket::serde::uuid::Uuid;
+use rocket::tokio::sync::broadcast::{self, Receiver, Sender};
+
+// Number of events kept in the channel for the slowest receivers.
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Event published by the picture routes.
+#[derive(Clone)]
+pub enum Event {
+    /// a new picture has been posted
+    Picture(Picture),
+    /// a new comment has been posted on a picture
+    Comment(Comment),
+    /// the like or dislike count of a picture has changed
+    Likes(LikeCount),
+}
+
+impl Event {
+    /// Name of the event in the stream.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Picture(_) => "picture",
+            Event::Comment(_) => "comment",
+            Event::Likes(_) => "likes",
+        }
+    }
+
+    /// Picture the event is about.
+    pub fn picture_id(&self) -> Uuid {
+        match self {
+            Event::Picture(picture) => picture.picture_id,
+            Event::Comment(comment) => comment.picture_id,
+            Event::Likes(likes) => likes.picture_id,
+        }
+    }
+}
+
+/// Broadcast channel of the events.
+pub struct Events {
+    sender: Sender<Event>,
+}
+
+impl Events {
+    /// Create a new events channel.
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish an event to the connected clients.
+    pub fn send(&self, event: Event) {
+        // this only fails when no client is listening
+        _ = self.sender.send(event);
+    }
+
+    /// Listen to the events published from now on.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,368 @@
+//! EXIF metadata of the uploaded pictures.
+//!
+//! Only the orientation tag is read so that pictures taken by phones are shown
+//! the right way up. Every other tag, including the GPS ones, is dropped since
+//! the pictures are always encoded again from their pixels before being saved.
+
+use crate::pictures::Format;
+use photon_rs::PhotonImage;
+
+// Header of the EXIF data in the JPEG APP1 segment.
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+// JPEG markers of the APP1 segment, of the start of scan and of the end of
+// image.
+const JPEG_APP1: u8 = 0xe1;
+const JPEG_SOS: u8 = 0xda;
+const JPEG_EOI: u8 = 0xd9;
+
+// TIFF tag of the orientation in the first IFD.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Find the EXIF data, which is a TIFF structure, in a JPEG file.
+fn find_in_jpeg(bytes: &[u8]) -> Option<&[u8]> {
+    let mut offset = 2;
+    while offset + 4 <= bytes.len() && bytes[offset] == 0xff {
+        let marker = bytes[offset + 1];
+        if marker == JPEG_SOS || marker == JPEG_EOI {
+            break;
+        }
+        let length =
+            u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        let segment = bytes.get(offset + 4..offset + 2 + length)?;
+        if marker == JPEG_APP1 && segment.starts_with(EXIF_HEADER) {
+            return Some(&segment[EXIF_HEADER.len()..]);
+        }
+        offset += 2 + length;
+    }
+    None
+}
+
+/// Find the EXIF data in the 'eXIf' chunk of a PNG file.
+fn find_in_png(bytes: &[u8]) -> Option<&[u8]> {
+    let mut offset = 8;
+    while offset + 8 <= bytes.len() {
+        let length =
+            u32::from_be_bytes(bytes[offset..offset + 4].try_into().ok()?)
+                as usize;
+        let kind = &bytes[offset + 4..offset + 8];
+        let data = bytes.get(offset + 8..offset + 8 + length)?;
+        match kind {
+            b"eXIf" => return Some(data),
+            b"IEND" => break,
+            _ => offset += 12 + length,
+        }
+    }
+    None
+}
+
+/// Find the EXIF data in the 'EXIF' chunk of a WebP file.
+fn find_in_webp(bytes: &[u8]) -> Option<&[u8]> {
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let kind = &bytes[offset..offset + 4];
+        let length =
+            u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().ok()?)
+                as usize;
+        let data = bytes.get(offset + 8..offset + 8 + length)?;
+        if kind == b"EXIF" {
+            // some encoders keep the JPEG header in the chunk
+            return Some(data.strip_prefix(EXIF_HEADER).unwrap_or(data));
+        }
+        // chunks are padded to an even size
+        offset += 8 + length + length % 2;
+    }
+    None
+}
+
+/// Find the EXIF data of a JPEG, PNG or WebP file.
+pub fn find(bytes: &[u8]) -> Option<&[u8]> {
+    match Format::sniff(bytes)? {
+        Format::Jpeg => find_in_jpeg(bytes),
+        Format::Png => find_in_png(bytes),
+        Format::Webp => find_in_webp(bytes),
+        Format::Heic => None,
+    }
+}
+
+/// Read the orientation tag of the picture. Returns None when the picture has
+/// no valid orientation.
+pub fn orientation(bytes: &[u8]) -> Option<u16> {
+    let tiff = find(bytes)?;
+    let little_endian = match tiff.get(..4)? {
+        [b'I', b'I', 42, 0] => true,
+        [b'M', b'M', 0, 42] => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match little_endian {
+            true => u16::from_le_bytes(bytes),
+            false => u16::from_be_bytes(bytes),
+        })
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match little_endian {
+            true => u32::from_le_bytes(bytes),
+            false => u32::from_be_bytes(bytes),
+        })
+    };
+
+    let ifd = read_u32(4)? as usize;
+    let entries = read_u16(ifd)? as usize;
+    (0..entries)
+        .map(|entry| ifd + 2 + entry * 12)
+        .find(|&entry| read_u16(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| read_u16(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// Transform the picture so that it is shown the right way up given its EXIF
+/// orientation.
+pub fn orient(picture: PhotonImage, orientation: u16) -> PhotonImage {
+    if orientation == 1 {
+        return picture;
+    }
+    let (width, height) = (picture.get_width(), picture.get_height());
+    // orientations above 4 swap the width and the height
+    let (new_width, new_height) = match orientation {
+        5..=8 => (height, width),
+        _ => (width, height),
+    };
+    let pixels = picture.get_raw_pixels();
+    let mut oriented = vec![0; pixels.len()];
+    for y in 0..new_height {
+        for x in 0..new_width {
+            // source pixel of the oriented one
+            let (src_x, src_y) = match orientation {
+                2 => (width - 1 - x, y),
+                3 => (width - 1 - x, height - 1 - y),
+                4 => (x, height - 1 - y),
+                5 => (y, x),
+                6 => (y, height - 1 - x),
+                7 => (width - 1 - y, height - 1 - x),
+                8 => (width - 1 - y, x),
+                _ => (x, y),
+            };
+            let src = ((src_y * width + src_x) * 4) as usize;
+            let dst = ((y * new_width + x) * 4) as usize;
+            oriented[dst..dst + 4].copy_from_slice(&pixels[src..src + 4]);
+        }
+    }
+    PhotonImage::new(oriented, new_width, new_height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::encode_jpeg;
+    use photon_rs::native;
+
+    /// TIFF structure with only the orientation tag in its first IFD.
+    fn tiff(little_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |value: u16| match little_endian {
+            true => value.to_le_bytes(),
+            false => value.to_be_bytes(),
+        };
+        let u32_bytes = |value: u32| match little_endian {
+            true => value.to_le_bytes(),
+            false => value.to_be_bytes(),
+        };
+        let mut tiff = match little_endian {
+            true => b"II".to_vec(),
+            false => b"MM".to_vec(),
+        };
+        tiff.extend(u16_bytes(42));
+        tiff.extend(u32_bytes(8));
+        tiff.extend(u16_bytes(1));
+        tiff.extend(u16_bytes(ORIENTATION_TAG));
+        tiff.extend(u16_bytes(3)); // SHORT type
+        tiff.extend(u32_bytes(1));
+        tiff.extend(u16_bytes(orientation));
+        tiff.extend([0, 0]);
+        tiff.extend(u32_bytes(0));
+        tiff
+    }
+
+    /// JPEG file with an APP0 segment and the given APP1 segment length.
+    fn jpeg_with_length(tiff: &[u8], length: u16) -> Vec<u8> {
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0];
+        jpeg.extend([0xff, JPEG_APP1]);
+        jpeg.extend(length.to_be_bytes());
+        jpeg.extend(EXIF_HEADER);
+        jpeg.extend(tiff);
+        jpeg.extend([0xff, JPEG_SOS, 0, 2, 0xff, JPEG_EOI]);
+        jpeg
+    }
+
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let length = 2 + EXIF_HEADER.len() + tiff.len();
+        jpeg_with_length(tiff, length as u16)
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend(kind);
+        chunk.extend(data);
+        chunk.extend([0; 4]); // the CRC is not checked
+        chunk
+    }
+
+    fn png(tiff: &[u8]) -> Vec<u8> {
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        png.extend(png_chunk(b"IHDR", &[0; 13]));
+        png.extend(png_chunk(b"eXIf", tiff));
+        png.extend(png_chunk(b"IEND", &[]));
+        png
+    }
+
+    fn webp_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp(exif: &[u8]) -> Vec<u8> {
+        // odd sized chunk to check the padding
+        let mut chunks = webp_chunk(b"VP8X", &[0; 9]);
+        chunks.extend(webp_chunk(b"EXIF", exif));
+        let mut webp = b"RIFF".to_vec();
+        webp.extend((chunks.len() as u32 + 4).to_le_bytes());
+        webp.extend(b"WEBP");
+        webp.extend(chunks);
+        webp
+    }
+
+    #[test]
+    fn orientation_in_both_byte_orders() {
+        for little_endian in [true, false] {
+            for expected in [6, 8] {
+                let tiff = tiff(little_endian, expected);
+                assert_eq!(orientation(&jpeg(&tiff)), Some(expected));
+                assert_eq!(orientation(&png(&tiff)), Some(expected));
+                assert_eq!(orientation(&webp(&tiff)), Some(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn orientation_with_exif_header_in_webp() {
+        let mut exif = EXIF_HEADER.to_vec();
+        exif.extend(tiff(true, 6));
+        assert_eq!(orientation(&webp(&exif)), Some(6));
+    }
+
+    #[test]
+    fn orientation_out_of_range() {
+        for invalid in [0, 9, u16::MAX] {
+            assert_eq!(orientation(&jpeg(&tiff(true, invalid))), None);
+        }
+    }
+
+    #[test]
+    fn no_exif() {
+        let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, JPEG_EOI];
+        assert_eq!(find(&jpeg), None);
+        assert_eq!(find(b"not a picture"), None);
+        assert_eq!(find(&[]), None);
+    }
+
+    #[test]
+    fn truncated_files() {
+        let tiff = tiff(false, 6);
+        for file in [jpeg(&tiff), png(&tiff), webp(&tiff)] {
+            for length in 0..file.len() {
+                // must not panic, the orientation is lost once cut
+                let _ = orientation(&file[..length]);
+            }
+        }
+        let jpeg = jpeg(&tiff);
+        assert_eq!(orientation(&jpeg[..20]), None);
+    }
+
+    #[test]
+    fn truncated_tiff() {
+        let tiff = tiff(true, 6);
+        // the orientation value ends at the 20th byte
+        for length in 0..20 {
+            assert_eq!(orientation(&jpeg(&tiff[..length])), None);
+        }
+    }
+
+    #[test]
+    fn invalid_segment_lengths() {
+        let tiff = tiff(true, 6);
+        for length in [0, 1, 2, 3, u16::MAX] {
+            assert_eq!(find(&jpeg_with_length(&tiff, length)), None);
+        }
+    }
+
+    #[test]
+    fn invalid_chunk_lengths() {
+        let mut png = png(&tiff(true, 6));
+        png[33..37].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(find(&png), None);
+
+        let mut webp = webp(&tiff(true, 6));
+        webp[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(find(&webp), None);
+    }
+
+    /// 2x3 picture whose pixels hold their index.
+    fn picture() -> PhotonImage {
+        let pixels = (0..6).flat_map(|index| [index, index, index, 255]);
+        PhotonImage::new(pixels.collect(), 2, 3)
+    }
+
+    fn indexes(picture: &PhotonImage) -> Vec<u8> {
+        picture
+            .get_raw_pixels()
+            .chunks(4)
+            .map(|pixel| pixel[0])
+            .collect()
+    }
+
+    #[test]
+    fn orient_each_orientation() {
+        let expected: [(u16, u32, u32, [u8; 6]); 8] = [
+            (1, 2, 3, [0, 1, 2, 3, 4, 5]),
+            (2, 2, 3, [1, 0, 3, 2, 5, 4]),
+            (3, 2, 3, [5, 4, 3, 2, 1, 0]),
+            (4, 2, 3, [4, 5, 2, 3, 0, 1]),
+            (5, 3, 2, [0, 2, 4, 1, 3, 5]),
+            (6, 3, 2, [4, 2, 0, 5, 3, 1]),
+            (7, 3, 2, [5, 3, 1, 4, 2, 0]),
+            (8, 3, 2, [1, 3, 5, 0, 2, 4]),
+        ];
+        for (orientation, width, height, pixels) in expected {
+            let oriented = orient(picture(), orientation);
+            assert_eq!(oriented.get_width(), width, "{}", orientation);
+            assert_eq!(oriented.get_height(), height, "{}", orientation);
+            assert_eq!(indexes(&oriented), pixels, "{}", orientation);
+        }
+    }
+
+    #[test]
+    fn encoded_pictures_have_no_exif() {
+        let tiff = tiff(true, 6);
+        let encoded = encode_jpeg(&picture(), 90).unwrap();
+        let mut uploaded = encoded[..2].to_vec();
+        uploaded.extend([0xff, JPEG_APP1]);
+        uploaded.extend(
+            (2 + EXIF_HEADER.len() as u16 + tiff.len() as u16).to_be_bytes(),
+        );
+        uploaded.extend(EXIF_HEADER);
+        uploaded.extend(&tiff);
+        uploaded.extend(&encoded[2..]);
+        assert_eq!(find(&uploaded), Some(tiff.as_slice()));
+
+        let decoded = native::open_image_from_bytes(&uploaded).unwrap();
+        let oriented = orient(decoded, orientation(&uploaded).unwrap());
+        assert_eq!(find(&encode_jpeg(&oriented, 90).unwrap()), None);
+    }
+}
@@ -12,28 +12,77 @@ mod auth;
 mod cache;
 mod config;
 mod cors;
+mod events;
+mod exif;
 mod mail;
+mod notification;
 mod payload;
 mod pictures;
 mod query;
+mod rate_limit;
 mod result;
 mod routes;
 mod uuid;
 mod validation;
 
 use auth::session;
-use cache::Cache;
+use cache::{Backend, Cache, MemoryStorage, PostgresStorage, Storage};
 use cors::Cors;
+use events::Events;
 use mail::Mailer;
 use payload::{Email, NewUser};
 use query::PostgresDb;
+use rate_limit::RateLimit;
 use rocket::fairing::AdHoc;
 use rocket::tokio::time::{sleep, Duration};
 use rocket_db_pools::Database;
-use routes::user::reset;
+use routes::user::{login, reset, two_factor};
+use std::env;
+use std::sync::Arc;
 
 #[launch]
 fn rocket() -> _ {
+    validation::load_banned_words();
+
+    // The session cookies are encrypted with this key so it must be set in
+    // production. Debug builds use a random key when it is empty.
+    if env::var("ROCKET_SECRET_KEY").unwrap_or_default().is_empty() {
+        if cfg!(debug_assertions) {
+            env::remove_var("ROCKET_SECRET_KEY");
+        } else {
+            panic!(
+                "missing ROCKET_SECRET_KEY env var, generate one with \
+                `openssl rand -base64 32`"
+            );
+        }
+    }
+
+    // The caches are managed once the database pool is available since it may
+    // be used as their storage backend
+    let cache_storage = AdHoc::try_on_ignite("Cache Storage", |rocket| async {
+        let storage: Arc<dyn Storage> = match *config::CACHE_STORAGE {
+            Backend::Memory => Arc::new(MemoryStorage::new()),
+            Backend::Postgres => match PostgresDb::fetch(&rocket) {
+                Some(db) => Arc::new(PostgresStorage::new((**db).clone())),
+                None => return Err(rocket),
+            },
+        };
+        Ok(rocket
+            .manage(Cache::<NewUser>::new("new_users", &storage))
+            .manage(Cache::<session::Active>::new("sessions", &storage))
+            .manage(Cache::<reset::Request>::new("reset_requests", &storage))
+            .manage(Cache::<Email>::new("new_emails", &storage))
+            .manage(Cache::<login::Pending>::new("pending_logins", &storage))
+            .manage(Cache::<two_factor::Enrollment>::new(
+                "totp_enrollments",
+                &storage,
+            ))
+            .manage(Cache::<rate_limit::Attempts>::new(
+                "rate_limits",
+                &storage,
+            )))
+    });
+
     // Remember to add the Cache cleanup call here when creating a managed Cache
     let cleanup_job =
         AdHoc::try_on_ignite("Cache Cleanup Job", |rocket| async {
@@ -42,7 +91,7 @@ fn rocket() -> _ {
                 .expect("Failed to get NewUser cache")
                 .clone();
             let sessions = rocket
-                .state::<Cache<session::Connected>>()
+                .state::<Cache<session::Active>>()
                 .expect("Failed to get connected session cache")
                 .clone();
             let reset_requests = rocket
@@ -53,12 +102,32 @@ fn rocket() -> _ {
                 .state::<Cache<Email>>()
                 .expect("Failed to get new email cache")
                 .clone();
+            let pending_logins = rocket
+                .state::<Cache<login::Pending>>()
+                .expect("Failed to get pending login cache")
+                .clone();
+            let enrollments = rocket
+                .state::<Cache<two_factor::Enrollment>>()
+                .expect("Failed to get two-factor enrollment cache")
+                .clone();
+            let rate_limits = rocket
+                .state::<Cache<rate_limit::Attempts>>()
+                .expect("Failed to get rate limit cache")
+                .clone();
             rocket::tokio::task::spawn(async move {
                 loop {
-                    new_users.cleanup();
-                    sessions.cleanup();
-                    reset_requests.cleanup();
-                    new_emails.cleanup();
+                    let cleaned = new_users
+                        .cleanup()
+                        .await
+                        .and(sessions.cleanup().await)
+                        .and(reset_requests.cleanup().await)
+                        .and(new_emails.cleanup().await)
+                        .and(pending_logins.cleanup().await)
+                        .and(enrollments.cleanup().await)
+                        .and(rate_limits.cleanup().await);
+                    if let Err(error) = cleaned {
+                        error!("failed to clean up the caches: {}", error);
+                    }
                     sleep(Duration::from_secs(*config::CACHE_CLEANUP_INTERVAL))
                         .await;
                 }
@@ -69,35 +138,56 @@ fn rocket() -> _ {
     rocket::build()
         .attach(PostgresDb::init())
         .manage(Mailer::new())
-        .manage(Cache::<NewUser>::new())
-        .manage(Cache::<session::Connected>::new())
-        .manage(Cache::<reset::Request>::new())
-        .manage(Cache::<Email>::new())
+        .manage(Events::new())
+        .attach(cache_storage)
         .attach(cleanup_job)
         .attach(Cors)
+        .attach(RateLimit)
         .mount("/", routes![routes::options])
         .mount("/user", routes![routes::user::register::post])
         .mount("/user", routes![routes::user::confirm::post])
         .mount("/user", routes![routes::user::login::post])
+        .mount("/user", routes![routes::user::login::post_two_factor])
         .mount("/user", routes![routes::user::logout::post])
         .mount("/user", routes![routes::user::reset::post])
         .mount("/user", routes![routes::user::reset::put])
         .mount("/user", routes![routes::user::email::post])
         .mount("/user", routes![routes::user::put])
         .mount("/user", routes![routes::user::get])
+        .mount("/user", routes![routes::user::delete])
+        .mount("/user", routes![routes::user::export::get])
+        .mount("/user", routes![routes::user::follow::put])
+        .mount("/user", routes![routes::user::follow::delete])
+        .mount("/user", routes![routes::user::likes::get])
+        .mount("/user", routes![routes::user::notifications::get])
+        .mount("/user", routes![routes::user::notifications::put_read])
+        .mount("/user", routes![routes::user::sessions::get])
+        .mount("/user", routes![routes::user::sessions::delete])
+        .mount("/user", routes![routes::user::sessions::delete_all])
+        .mount("/user", routes![routes::user::two_factor::post])
+        .mount("/user", routes![routes::user::two_factor::put])
+        .mount("/user", routes![routes::user::two_factor::delete])
+        .mount("/users", routes![routes::users::get])
         .mount("/picture", routes![routes::picture::like::put])
         .mount("/picture", routes![routes::picture::like::delete])
+        .mount("/picture", routes![routes::picture::likes::get])
         .mount("/picture", routes![routes::picture::comment::post])
+        .mount("/picture", routes![routes::picture::comment::put])
+        .mount("/picture", routes![routes::picture::comment::delete])
         .mount("/picture", routes![routes::picture::comments::get])
         .mount("/picture", routes![routes::picture::post])
+        .mount("/picture", routes![routes::picture::post_layers])
         .mount("/picture", routes![routes::picture::delete])
         .mount("/pictures", routes![routes::pictures::superposable::get])
+        .mount("/pictures", routes![routes::pictures::superposable::post])
         .mount("/pictures", routes![routes::pictures::get])
+        .mount("/events", routes![routes::events::get])
         .register("/", catchers![result::default])
         .register("/", catchers![result::bad_request])
         .register("/", catchers![result::unauthorized])
         .register("/", catchers![result::forbidden])
         .register("/", catchers![result::not_found])
         .register("/", catchers![result::unprocessable_entity])
+        .register("/", catchers![result::too_many_requests])
         .register("/", catchers![result::internal_error])
 }
@@ -0,0 +1,18 @@
+//! Kinds of notifications sent to the users
+
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx;
+
+/// Event a user is notified of
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(crate = "rocket::serde")]
+#[sqlx(type_name = "notification_kind", rename_all = "lowercase")] // For postgresql
+#[serde(rename_all = "lowercase")] // For json
+pub enum Kind {
+    /// someone commented on the user's picture
+    Comment,
+    /// someone liked the user's picture
+    Like,
+    /// someone mentioned the user in a comment
+    Mention,
+}
@@ -1,5 +1,6 @@
 //! Re-usable payloads for the routes' incoming or outgoing json data.
 
+use crate::notification::Kind;
 use crate::pictures::Superposable;
 use rocket::serde::{uuid::Uuid, Deserialize, Serialize};
 use std::fmt;
@@ -40,7 +41,7 @@ impl fmt::Display for Token {
 }
 
 /// New user data for user registration.
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct NewUser {
     pub username: String,
@@ -55,15 +56,93 @@ pub struct UserProfile {
     pub username: String,
     pub email: String,
     pub email_notifications: bool,
+    pub comment_notifications: bool,
+    pub like_notifications: bool,
+    pub mention_notifications: bool,
+    pub two_factor: bool,
 }
 
-/// Picture data
+/// Login response. The token is given when a two-factor code is required.
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Login {
+    pub response: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub two_factor_token: Option<Uuid>,
+}
+
+/// Two-factor authentication secret to register in an authenticator app
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct TwoFactorSecret {
+    pub secret: String,
+    pub uri: String,
+}
+
+/// Two-factor authentication or recovery code
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Code {
+    pub code: String,
+}
+
+/// One-time recovery codes
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Public profile of a user, the counts are the ones received on the user's
+/// pictures
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
+pub struct PublicProfile {
+    pub username: String,
+    pub creation_ts: i64,
+    pub picture_count: i64,
+    pub like_count: i64,
+    pub dislike_count: i64,
+    pub comment_count: i64,
+    pub favorite_superposable: Option<Superposable>,
+    pub follower_count: i64,
+    pub following_count: i64,
+}
+
+/// Account data of the personal data export
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Account {
+    pub account_id: Uuid,
+    pub email: String,
+    pub username: String,
+    pub email_notifications: bool,
+    pub comment_notifications: bool,
+    pub like_notifications: bool,
+    pub mention_notifications: bool,
+    pub two_factor: bool,
+}
+
+/// Active session data
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Session {
+    pub session_id: Uuid,
+    pub creation_ts: i64,
+    pub last_seen: i64,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub current: bool,
+}
+
+/// Picture data
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
 pub struct Picture {
     pub picture_id: Uuid,
     pub account_id: Uuid,
-    pub superposable: Superposable,
+    /// superposables of the picture from the bottom layer to the top one
+    pub superposables: Vec<Superposable>,
     pub creation_ts: i64,
     pub author: String,
     pub like_count: i64,
@@ -71,6 +150,8 @@ pub struct Picture {
     pub comment_count: i64,
     pub liked: Option<bool>,
     pub disliked: Option<bool>,
+    /// position of the picture to get the next ones with the 'after' parameter
+    pub cursor: String,
 }
 
 /// Picture ID
@@ -81,12 +162,78 @@ pub struct PictureId {
 }
 
 /// Comment data
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct Comment {
+    pub comment_id: Uuid,
+    pub parent_comment_id: Option<Uuid>,
     pub picture_id: Uuid,
     pub account_id: Uuid,
     pub creation_ts: i64,
+    pub edited_ts: Option<i64>,
     pub content: String,
     pub author: String,
+    /// depth of the comment in the picture's thread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<i32>,
+}
+
+/// Like and dislike counts of a picture
+#[derive(Serialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct LikeCount {
+    pub picture_id: Uuid,
+    pub like_count: i64,
+    pub dislike_count: i64,
+}
+
+/// Like data, the value is false for a dislike
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Like {
+    pub picture_id: Uuid,
+    pub value: bool,
+    pub creation_ts: i64,
+}
+
+/// User who liked or disliked a picture, the value is false for a dislike
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Liker {
+    pub username: String,
+    pub value: bool,
+    pub creation_ts: i64,
+}
+
+/// Notification data
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Notification {
+    pub notification_id: Uuid,
+    pub kind: Kind,
+    pub author: String,
+    pub picture_id: Uuid,
+    pub creation_ts: i64,
+    pub read: bool,
+}
+
+/// Page of notifications with the total number of unread ones
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Notifications {
+    pub unread_count: i64,
+    pub notifications: Vec<Notification>,
+}
+
+/// Superposable of the catalog with its default placement
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CatalogSuperposable {
+    pub name: String,
+    pub category: String,
+    pub x: i32,
+    /// top side of the superposable, at the bottom of the picture if null
+    pub y: Option<i32>,
+    pub scale: f64,
+    pub rotation: f64,
 }
@@ -1,39 +1,43 @@
-//! Constants and enums used to manipulate pictures and superposables
+//! Constants and types used to manipulate pictures and superposables
 
+use crate::uuid::SqlxUuid;
+use crate::validation;
+use data_encoding::BASE64URL_NOPAD;
+use image::codecs::jpeg::JpegEncoder;
+use image::ColorType;
+use photon_rs::transform::{self, SamplingFilter};
+use photon_rs::PhotonImage;
+use rocket::form::{self, FromFormField, ValueField};
 use rocket::request::FromParam;
 use rocket::serde::Serialize;
-use rocket_db_pools::sqlx;
+use std::fmt;
 use std::str::FromStr;
-use strum::{self, AsRefStr, EnumIter, EnumString};
-
-// Superposable picture names
-#[derive(
-    Clone,
-    Debug,
-    PartialEq,
-    Ord,
-    PartialOrd,
-    Eq,
-    EnumString,
-    AsRefStr,
-    EnumIter,
-    Serialize,
-    sqlx::Type,
-    FromFormField,
-)]
-#[strum(serialize_all = "lowercase")] // Every Superposable name is in lowercase
+
+/// Name of a superposable of the catalog
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 #[serde(crate = "rocket::serde")]
-#[sqlx(type_name = "superposable", rename_all = "lowercase")] // For postgresql
-#[serde(rename_all = "lowercase")] // For json
-pub enum Superposable {
-    Chic,
-    Cry,
-    Honk,
-    Rage,
-    Sad,
-    Smirk,
-    Stoned,
-    Sweat,
+#[serde(transparent)]
+pub struct Superposable(String);
+
+impl AsRef<str> for Superposable {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Superposable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Superposable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validation::superposable(s)?;
+        Ok(Superposable(s.to_string()))
+    }
 }
 
 impl<'a> FromParam<'a> for Superposable {
@@ -46,3 +50,528 @@ impl<'a> FromParam<'a> for Superposable {
         }
     }
 }
+
+impl<'r> FromFormField<'r> for Superposable {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        field
+            .value
+            .parse()
+            .map_err(|message: String| form::Error::validation(message).into())
+    }
+}
+
+// Major brands of the HEIF container for HEIC pictures.
+const HEIC_BRANDS: [&[u8]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+// Offset of the flags of the extended WebP format and animation flag.
+const WEBP_VP8X_FLAGS_OFFSET: usize = 20;
+const WEBP_ANIMATION_FLAG: u8 = 0x02;
+
+// Content types accepted for the user pictures.
+pub const PICTURE_SUBTYPES: [&str; 5] = ["jpeg", "png", "webp", "heic", "heif"];
+
+/// Formats of the user pictures
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Webp,
+    Heic,
+}
+
+impl Format {
+    /// Find the format of a picture from its first bytes.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0xff, 0xd8, 0xff, ..] => Some(Format::Jpeg),
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => {
+                Some(Format::Png)
+            }
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+                Some(Format::Webp)
+            }
+            [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..]
+                if brand.len() >= 4 && HEIC_BRANDS.contains(&&brand[..4]) =>
+            {
+                Some(Format::Heic)
+            }
+            _ => None,
+        }
+    }
+
+    /// Check if a WebP picture is animated which cannot be converted.
+    pub fn is_animated_webp(bytes: &[u8]) -> bool {
+        bytes.get(12..16) == Some(b"VP8X")
+            && bytes
+                .get(WEBP_VP8X_FLAGS_OFFSET)
+                .is_some_and(|flags| flags & WEBP_ANIMATION_FLAG != 0)
+    }
+}
+
+/// Encode the picture as a JPEG file of the given quality. Any metadata of the
+/// original file is lost since only the pixels are encoded. The transparent
+/// parts of the picture are flattened on a white background since JPEG has no
+/// alpha channel.
+pub fn encode_jpeg(picture: &PhotonImage, quality: u8) -> Result<Vec<u8>, ()> {
+    let pixels: Vec<u8> = picture
+        .get_raw_pixels()
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = u16::from(pixel[3]);
+            pixel[..3].iter().map(move |&color| {
+                ((u16::from(color) * alpha + 255 * (255 - alpha)) / 255) as u8
+            })
+        })
+        .collect();
+    let mut bytes = Vec::new();
+    JpegEncoder::new_with_quality(&mut bytes, quality)
+        .encode(
+            &pixels,
+            picture.get_width(),
+            picture.get_height(),
+            ColorType::Rgb8,
+        )
+        .map_err(|_| ())?;
+    Ok(bytes)
+}
+
+// Bounds of the superposable scale factor
+pub const MIN_SCALE: f64 = 0.1;
+pub const MAX_SCALE: f64 = 4.0;
+
+/// Placement of the superposable on the user picture. The superposable is
+/// scaled and rotated around its center before being put at the given
+/// position.
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    /// left side of the superposable in the user picture
+    pub x: u32,
+    /// top side of the superposable, at the bottom of the picture if None
+    pub y: Option<u32>,
+    /// size factor of the superposable
+    pub scale: f64,
+    /// clockwise rotation of the superposable in degrees
+    pub rotation: f64,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            x: 0,
+            y: None,
+            scale: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Placement {
+    /// Size of the given rectangle once rotated, which is the bounding box of
+    /// the rotated rectangle.
+    fn rotated_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (width, height) = (width as f64, height as f64);
+        (
+            (width * cos.abs() + height * sin.abs()).round() as u32,
+            (width * sin.abs() + height * cos.abs()).round() as u32,
+        )
+    }
+
+    /// Size of a superposable of the given dimensions once placed.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        self.rotated_size(
+            (width as f64 * self.scale).round() as u32,
+            (height as f64 * self.scale).round() as u32,
+        )
+    }
+
+    /// Scale and rotate the superposable.
+    pub fn apply(&self, superposable: PhotonImage) -> PhotonImage {
+        let superposable = if self.scale == 1.0 {
+            superposable
+        } else {
+            transform::resize(
+                &superposable,
+                (superposable.get_width() as f64 * self.scale).round() as u32,
+                (superposable.get_height() as f64 * self.scale).round() as u32,
+                SamplingFilter::Lanczos3,
+            )
+        };
+        if self.rotation % 360.0 == 0.0 {
+            superposable
+        } else {
+            self.rotate(&superposable)
+        }
+    }
+
+    /// Rotate the superposable around its center. The new pixels around the
+    /// rotated superposable are transparent.
+    fn rotate(&self, image: &PhotonImage) -> PhotonImage {
+        let (width, height) = (image.get_width(), image.get_height());
+        let (new_width, new_height) = self.rotated_size(width, height);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let pixels = image.get_raw_pixels();
+        let mut rotated = vec![0; (new_width * new_height * 4) as usize];
+        for y in 0..new_height {
+            for x in 0..new_width {
+                // source pixel given by the inverse rotation around the centers
+                let dx = x as f64 + 0.5 - new_width as f64 / 2.0;
+                let dy = y as f64 + 0.5 - new_height as f64 / 2.0;
+                let src_x = dx * cos + dy * sin + width as f64 / 2.0;
+                let src_y = dy * cos - dx * sin + height as f64 / 2.0;
+                if src_x < 0.0
+                    || src_y < 0.0
+                    || src_x >= width as f64
+                    || src_y >= height as f64
+                {
+                    continue;
+                }
+                let src = ((src_y as u32 * width + src_x as u32) * 4) as usize;
+                let dst = ((y * new_width + x) * 4) as usize;
+                rotated[dst..dst + 4].copy_from_slice(&pixels[src..src + 4]);
+            }
+        }
+        PhotonImage::new(rotated, new_width, new_height)
+    }
+}
+
+// Maximum number of superposables on a picture
+pub const MAX_LAYERS: usize = 8;
+
+/// Superposable put on a user picture. It is given to the upload route as
+/// 'superposable[,x,y[,scale[,rotation]]]'. The default placement of the
+/// superposable in the catalog is used when none is given.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub superposable: Superposable,
+    pub placement: Option<Placement>,
+}
+
+impl FromStr for Layer {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(',');
+        let superposable = fields.next().ok_or(())?.parse().map_err(|_| ())?;
+        let x = match fields.next() {
+            Some(x) => x.parse().map_err(|_| ())?,
+            None => {
+                return Ok(Layer {
+                    superposable,
+                    placement: None,
+                })
+            }
+        };
+        let mut placement = Placement {
+            x,
+            y: Some(fields.next().ok_or(())?.parse().map_err(|_| ())?),
+            ..Placement::default()
+        };
+        if let Some(scale) = fields.next() {
+            placement.scale = scale.parse().map_err(|_| ())?;
+        }
+        if let Some(rotation) = fields.next() {
+            placement.rotation = rotation.parse().map_err(|_| ())?;
+        }
+        if fields.next().is_some() {
+            return Err(());
+        }
+        Ok(Layer {
+            superposable,
+            placement: Some(placement),
+        })
+    }
+}
+
+/// Layer with the file of its superposable and its final placement.
+#[derive(Clone, Debug)]
+pub struct PlacedLayer {
+    pub superposable: Superposable,
+    pub file: String,
+    pub placement: Placement,
+}
+
+impl<'r> FromFormField<'r> for Layer {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        field
+            .value
+            .parse()
+            .map_err(|_| form::Error::validation("invalid layer").into())
+    }
+}
+
+// Seconds for which a picture needs ten times more votes to keep its hot
+// score. This is about half a day.
+const HOT_SCORE_DECAY: u32 = 45000;
+
+/// Gallery sorting orders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+pub enum Sort {
+    Newest,
+    Oldest,
+    #[field(value = "most_liked")]
+    MostLiked,
+    #[field(value = "most_commented")]
+    MostCommented,
+    /// net likes weighted by the picture's age
+    Hot,
+    /// total votes weighted by how evenly they are split
+    Controversial,
+}
+
+impl Sort {
+    /// Sql expression of the value the pictures are sorted by. It must not
+    /// depend on the time of the request so that cursors stay valid.
+    pub fn value(&self) -> String {
+        let creation = "EXTRACT(EPOCH FROM pictures.creation_ts)::float8";
+        let likes = "COUNT(CASE WHEN likes.value = TRUE THEN 1 END)";
+        let dislikes = "COUNT(CASE WHEN likes.value = FALSE THEN 1 END)";
+        match self {
+            Sort::Newest | Sort::Oldest => creation.to_string(),
+            Sort::MostLiked => format!("{}::float8", likes),
+            Sort::MostCommented => {
+                "COALESCE(comment_counts.comment_count, 0)::float8".to_string()
+            }
+            Sort::Hot => format!(
+                "SIGN({likes} - {dislikes})::float8
+				* LOG(GREATEST(ABS({likes} - {dislikes}), 1)::float8)
+				+ {creation} / {decay}",
+                likes = likes,
+                dislikes = dislikes,
+                creation = creation,
+                decay = HOT_SCORE_DECAY,
+            ),
+            Sort::Controversial => format!(
+                "({likes} + {dislikes})::float8
+				* LEAST({likes}, {dislikes})::float8
+				/ GREATEST({likes}, {dislikes}, 1)::float8",
+                likes = likes,
+                dislikes = dislikes,
+            ),
+        }
+    }
+
+    /// Are the pictures sorted by increasing value.
+    pub fn is_ascending(&self) -> bool {
+        *self == Sort::Oldest
+    }
+}
+
+/// Time window of the gallery
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromFormField)]
+pub enum Window {
+    Day,
+    Week,
+    All,
+}
+
+impl Window {
+    /// Duration of the window in seconds.
+    pub fn duration(&self) -> Option<i64> {
+        match self {
+            Window::Day => Some(24 * 60 * 60),
+            Window::Week => Some(7 * 24 * 60 * 60),
+            Window::All => None,
+        }
+    }
+}
+
+/// Position of a picture in the gallery for keyset pagination. It is given to
+/// the clients as an opaque string to be sent back with the 'after' parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor {
+    /// value of the picture for the requested sorting order
+    pub value: f64,
+    pub picture_id: SqlxUuid,
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cursor = format!("{}:{}", self.value, self.picture_id);
+        write!(f, "{}", BASE64URL_NOPAD.encode(cursor.as_bytes()))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cursor = BASE64URL_NOPAD.decode(s.as_bytes()).map_err(|_| ())?;
+        let cursor = String::from_utf8(cursor).map_err(|_| ())?;
+        let (value, picture_id) = cursor.split_once(':').ok_or(())?;
+        let value: f64 = value.parse().map_err(|_| ())?;
+        // NaN cannot be compared to the sort values of the pictures
+        if !value.is_finite() {
+            return Err(());
+        }
+        Ok(Cursor {
+            value,
+            picture_id: picture_id.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+impl<'r> FromFormField<'r> for Cursor {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        field
+            .value
+            .parse()
+            .map_err(|_| form::Error::validation("invalid cursor").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(value: f64) -> Cursor {
+        Cursor {
+            value,
+            picture_id: "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        for value in [0.0, -1.5, 1676462400.123456, f64::MAX, f64::MIN_POSITIVE]
+        {
+            let cursor = cursor(value);
+            assert_eq!(cursor.to_string().parse(), Ok(cursor));
+        }
+    }
+
+    #[test]
+    fn invalid_cursors() {
+        let encode = |cursor: &str| BASE64URL_NOPAD.encode(cursor.as_bytes());
+        let picture_id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        for invalid in [
+            String::new(),
+            String::from("not a cursor!"),
+            encode("1.5"),
+            encode("1.5:not a uuid"),
+            encode(&format!("one:{}", picture_id)),
+            encode(&format!("NaN:{}", picture_id)),
+            encode(&format!("inf:{}", picture_id)),
+            encode(&format!("-inf:{}", picture_id)),
+        ] {
+            assert_eq!(invalid.parse::<Cursor>(), Err(()), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn layer_without_placement() {
+        let layer: Layer = "pepe_2".parse().unwrap();
+        assert_eq!(layer.superposable.as_ref(), "pepe_2");
+        assert!(layer.placement.is_none());
+    }
+
+    #[test]
+    fn layer_with_placement() {
+        let layer: Layer = "pepe,10,20".parse().unwrap();
+        let placement = layer.placement.unwrap();
+        assert_eq!((placement.x, placement.y), (10, Some(20)));
+        assert_eq!((placement.scale, placement.rotation), (1.0, 0.0));
+
+        let layer: Layer = "pepe,0,4294967295,0.5,-90".parse().unwrap();
+        let placement = layer.placement.unwrap();
+        assert_eq!((placement.x, placement.y), (0, Some(u32::MAX)));
+        assert_eq!((placement.scale, placement.rotation), (0.5, -90.0));
+    }
+
+    #[test]
+    fn invalid_layers() {
+        for invalid in [
+            "",
+            "Pepe",
+            "pepe,",
+            "pepe,10",
+            "pepe,-1,20",
+            "pepe,10,-20",
+            "pepe,4294967296,20",
+            "pepe,10,4294967296",
+            "pepe,10,20,big",
+            "pepe,10,20,1,left",
+            "pepe,10,20,1,0,",
+        ] {
+            assert!(invalid.parse::<Layer>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn placed_size() {
+        let placement = |scale, rotation| Placement {
+            scale,
+            rotation,
+            ..Placement::default()
+        };
+        let expected = [
+            (1.0, 0.0, (200, 100)),
+            (1.0, 90.0, (100, 200)),
+            (1.0, -90.0, (100, 200)),
+            (1.0, 180.0, (200, 100)),
+            (1.0, 450.0, (100, 200)),
+            (1.0, 45.0, (212, 212)),
+            (0.5, 30.0, (112, 93)),
+            (2.0, 0.0, (400, 200)),
+        ];
+        for (scale, rotation, size) in expected {
+            let placement = placement(scale, rotation);
+            assert_eq!(placement.size(200, 100), size, "{:?}", placement);
+        }
+        // the size saturates instead of overflowing
+        let placement = placement(MAX_SCALE, 0.0);
+        assert_eq!(placement.size(u32::MAX, 1), (u32::MAX, 4));
+    }
+
+    /// WebP file with a VP8X chunk holding the given flags.
+    fn extended_webp(flags: u8) -> Vec<u8> {
+        let mut webp = b"RIFF\x16\0\0\0WEBPVP8X\x0a\0\0\0".to_vec();
+        webp.extend([flags, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        webp
+    }
+
+    #[test]
+    fn sniff_formats() {
+        let jpeg = [0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F', b'I', b'F'];
+        assert_eq!(Format::sniff(&jpeg), Some(Format::Jpeg));
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR";
+        assert_eq!(Format::sniff(png), Some(Format::Png));
+        let webp = b"RIFF\x24\0\0\0WEBPVP8 \x18\0\0\0";
+        assert_eq!(Format::sniff(webp), Some(Format::Webp));
+        assert_eq!(Format::sniff(&extended_webp(0)), Some(Format::Webp));
+        for brand in HEIC_BRANDS {
+            let mut heic = b"\0\0\0\x18ftyp".to_vec();
+            heic.extend(brand);
+            assert_eq!(Format::sniff(&heic), Some(Format::Heic));
+        }
+    }
+
+    #[test]
+    fn sniff_unknown_formats() {
+        for unknown in [
+            &b""[..],
+            b"\xff\xd8",
+            b"GIF89a\x01\0\x01\0",
+            b"RIFF\x24\0\0\0WAVEfmt ",
+            b"\0\0\0\x18ftypavif",
+            b"\0\0\0\x18ftyphe",
+            b"not a picture",
+        ] {
+            assert_eq!(Format::sniff(unknown), None, "{:?}", unknown);
+        }
+    }
+
+    #[test]
+    fn animated_webp() {
+        assert!(Format::is_animated_webp(&extended_webp(
+            WEBP_ANIMATION_FLAG
+        )));
+        // alpha and EXIF flags only
+        assert!(!Format::is_animated_webp(&extended_webp(0x18)));
+        assert!(!Format::is_animated_webp(b"RIFF\x24\0\0\0WEBPVP8 \x18\0"));
+        let webp = extended_webp(WEBP_ANIMATION_FLAG);
+        assert!(!Format::is_animated_webp(&webp[..WEBP_VP8X_FLAGS_OFFSET]));
+    }
+}
@@ -3,17 +3,21 @@
 use crate::uuid::{from_sqlx_to_serde, SqlxUuid};
 use crate::{
     auth::password,
-    payload::{Comment, NewUser, Picture},
-    pictures::Superposable,
+    notification::Kind,
+    payload::{
+        CatalogSuperposable, Comment, Like, LikeCount, Liker, NewUser,
+        Notification, Picture, PublicProfile,
+    },
+    pictures::{Cursor, PlacedLayer, Placement, Sort, Superposable},
 };
 use rocket::http::Status;
-use rocket_db_pools::sqlx::{self, PgPool};
+use rocket_db_pools::sqlx::{self, Acquire, PgConnection, PgPool};
 use rocket_db_pools::{Connection, Database};
 
 pub mod types {
     use super::sqlx::{self, types::time::OffsetDateTime};
     use super::SqlxUuid;
-    use crate::pictures::Superposable;
+    use crate::notification::Kind;
 
     /// An account instance from the 'accounts' table.
     #[derive(sqlx::FromRow)]
@@ -23,6 +27,11 @@ pub mod types {
         pub username: String,
         pub password_hash: String,
         pub email_notifications: bool,
+        pub comment_notifications: bool,
+        pub like_notifications: bool,
+        pub mention_notifications: bool,
+        pub admin: bool,
+        pub totp_secret: Option<String>,
     }
 
     /// A picture from the GET pictures request
@@ -30,7 +39,7 @@ pub mod types {
     pub struct DbPicture {
         pub picture_id: SqlxUuid,
         pub account_id: SqlxUuid,
-        pub superposable: Superposable,
+        pub superposables: Vec<String>,
         pub creation_ts: OffsetDateTime,
         pub author: String,
         pub like_count: i64,
@@ -38,6 +47,7 @@ pub mod types {
         pub comment_count: i64,
         pub liked: Option<bool>,
         pub disliked: Option<bool>,
+        pub sort_value: f64,
     }
 
     /// A picture id from the POST picture request
@@ -49,11 +59,96 @@ pub mod types {
     /// A comment from the GET comments request
     #[derive(sqlx::FromRow, Debug)]
     pub struct DbComment {
+        pub comment_id: SqlxUuid,
+        pub parent_comment_id: Option<SqlxUuid>,
         pub picture_id: SqlxUuid,
         pub account_id: SqlxUuid,
         pub creation_ts: OffsetDateTime,
+        pub edited_ts: Option<OffsetDateTime>,
         pub content: String,
         pub author: String,
+        /// depth of the comment in the thread, only given by the comments
+        /// query
+        #[sqlx(default)]
+        pub depth: Option<i32>,
+    }
+
+    /// Public profile statistics of a user
+    #[derive(sqlx::FromRow)]
+    pub struct DbPublicProfile {
+        pub username: String,
+        pub creation_ts: OffsetDateTime,
+        pub picture_count: i64,
+        pub like_count: i64,
+        pub dislike_count: i64,
+        pub comment_count: i64,
+        pub favorite_superposable: Option<String>,
+        pub follower_count: i64,
+        pub following_count: i64,
+    }
+
+    /// A superposable of the catalog
+    #[derive(sqlx::FromRow)]
+    pub struct DbSuperposable {
+        pub name: String,
+        pub file: String,
+        pub category: String,
+        pub x: i32,
+        pub y: Option<i32>,
+        pub scale: f64,
+        pub rotation: f64,
+    }
+
+    /// A notification of a user
+    #[derive(sqlx::FromRow)]
+    pub struct DbNotification {
+        pub notification_id: SqlxUuid,
+        pub kind: Kind,
+        pub author: String,
+        pub picture_id: SqlxUuid,
+        pub creation_ts: OffsetDateTime,
+        pub read: bool,
+    }
+
+    /// A like or dislike given by a user
+    #[derive(sqlx::FromRow)]
+    pub struct DbLike {
+        pub picture_id: SqlxUuid,
+        pub value: bool,
+        pub creation_ts: OffsetDateTime,
+    }
+
+    /// A user who liked or disliked a picture
+    #[derive(sqlx::FromRow)]
+    pub struct DbLiker {
+        pub username: String,
+        pub value: bool,
+        pub creation_ts: OffsetDateTime,
+    }
+}
+
+impl From<&types::DbSuperposable> for CatalogSuperposable {
+    fn from(db_superposable: &types::DbSuperposable) -> Self {
+        CatalogSuperposable {
+            name: db_superposable.name.clone(),
+            category: db_superposable.category.clone(),
+            x: db_superposable.x,
+            y: db_superposable.y,
+            scale: db_superposable.scale,
+            rotation: db_superposable.rotation,
+        }
+    }
+}
+
+impl types::DbSuperposable {
+    /// Default placement of the superposable.
+    pub fn placement(&self) -> Placement {
+        Placement {
+            x: self.x as u32,
+            y: self.y.map(|y| y as u32),
+            scale: self.scale,
+            rotation: self.rotation,
+        }
     }
 }
 
@@ -62,7 +157,22 @@ impl From<&types::DbPicture> for Picture {
         Picture {
             picture_id: from_sqlx_to_serde(&db_picture.picture_id),
             account_id: from_sqlx_to_serde(&db_picture.account_id),
-            superposable: db_picture.superposable.clone(),
+            superposables: db_picture
+                .superposables
+                .iter()
+                // the names are checked before being stored, a bad one is
+                // left out rather than failing the whole request
+                .filter_map(|superposable| match superposable.parse() {
+                    Ok(superposable) => Some(superposable),
+                    Err(_) => {
+                        error!(
+                            "invalid superposable name '{}' for picture {}",
+                            superposable, db_picture.picture_id
+                        );
+                        None
+                    }
+                })
+                .collect(),
             creation_ts: db_picture.creation_ts.unix_timestamp(),
             author: db_picture.author.clone(),
             like_count: db_picture.like_count,
@@ -70,6 +180,30 @@ impl From<&types::DbPicture> for Picture {
             comment_count: db_picture.comment_count,
             liked: db_picture.liked,
             disliked: db_picture.disliked,
+            cursor: Cursor {
+                value: db_picture.sort_value,
+                picture_id: db_picture.picture_id,
+            }
+            .to_string(),
+        }
+    }
+}
+
+impl From<&types::DbComment> for Comment {
+    fn from(db_comment: &types::DbComment) -> Self {
+        Comment {
+            comment_id: from_sqlx_to_serde(&db_comment.comment_id),
+            parent_comment_id: db_comment
+                .parent_comment_id
+                .as_ref()
+                .map(from_sqlx_to_serde),
+            picture_id: from_sqlx_to_serde(&db_comment.picture_id),
+            account_id: from_sqlx_to_serde(&db_comment.account_id),
+            creation_ts: db_comment.creation_ts.unix_timestamp(),
+            edited_ts: db_comment.edited_ts.map(|ts| ts.unix_timestamp()),
+            content: db_comment.content.clone(),
+            author: db_comment.author.clone(),
+            depth: db_comment.depth,
         }
     }
 }
@@ -78,6 +212,149 @@ impl From<&types::DbPicture> for Picture {
 #[database("postgres")]
 pub struct PostgresDb(PgPool);
 
+/// Queries for the postgres storage backend of the [`Cache`](crate::cache).
+///
+/// These run directly on the pool since the storage is shared by every route
+/// and lives outside of the request's database connection.
+pub mod cache {
+    use super::sqlx::{self, PgPool};
+    use std::time::Duration;
+
+    /// Set a value and return the old one if it was not expired.
+    pub async fn set(
+        pool: &PgPool,
+        namespace: &str,
+        key: &str,
+        value: &str,
+        lifetime: Duration,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let query = "
+			WITH old_value AS (
+				SELECT value FROM cache
+				WHERE namespace = $1 AND key = $2 AND expiration_ts > NOW()
+			), new_value AS (
+				INSERT INTO cache (namespace, key, value, expiration_ts)
+				VALUES ($1, $2, $3, NOW() + $4 * INTERVAL '1 second')
+				ON CONFLICT (namespace, key)
+				DO UPDATE SET value = $3, expiration_ts = EXCLUDED.expiration_ts
+			)
+			SELECT value FROM old_value;
+		";
+
+        sqlx::query_scalar::<_, String>(query)
+            .bind(namespace)
+            .bind(key)
+            .bind(value)
+            .bind(lifetime.as_secs_f64())
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Get a value if it is not expired.
+    pub async fn get(
+        pool: &PgPool,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let query = "
+			SELECT value FROM cache
+			WHERE namespace = $1 AND key = $2 AND expiration_ts > NOW();
+		";
+
+        sqlx::query_scalar::<_, String>(query)
+            .bind(namespace)
+            .bind(key)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Delete a value and return it if it was not expired.
+    pub async fn del(
+        pool: &PgPool,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let query = "
+			WITH deleted AS (
+				DELETE FROM cache WHERE namespace = $1 AND key = $2
+				RETURNING value, expiration_ts
+			)
+			SELECT value FROM deleted WHERE expiration_ts > NOW();
+		";
+
+        sqlx::query_scalar::<_, String>(query)
+            .bind(namespace)
+            .bind(key)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Check if a given key exists.
+    pub async fn exists(
+        pool: &PgPool,
+        namespace: &str,
+        key: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let query = "SELECT key FROM cache WHERE namespace = $1 AND key = $2;";
+        let row = sqlx::query(query)
+            .bind(namespace)
+            .bind(key)
+            .fetch_optional(pool)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    /// List every valid value whose key starts with the given prefix.
+    pub async fn list(
+        pool: &PgPool,
+        namespace: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let query = "
+			SELECT value FROM cache
+			WHERE namespace = $1 AND STARTS_WITH(key, $2)
+				AND expiration_ts > NOW();
+		";
+
+        sqlx::query_scalar::<_, String>(query)
+            .bind(namespace)
+            .bind(prefix)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Delete every expired value of the namespace.
+    pub async fn cleanup(
+        pool: &PgPool,
+        namespace: &str,
+    ) -> Result<(), sqlx::Error> {
+        let query =
+            "DELETE FROM cache WHERE namespace = $1 AND expiration_ts <= NOW();";
+        sqlx::query(query).bind(namespace).execute(pool).await?;
+        Ok(())
+    }
+}
+
+/// Get the http status matching a database error
+pub fn error_status(error: &sqlx::Error) -> Status {
+    match error {
+        sqlx::Error::RowNotFound => Status::NotFound,
+        sqlx::Error::PoolTimedOut => Status::ServiceUnavailable,
+        sqlx::Error::Database(error) => match error.code().as_deref() {
+            // foreign key violation, the referenced row does not exist
+            Some("23503") => Status::NotFound,
+            // not null and check violations
+            Some("23502") | Some("23514") => Status::BadRequest,
+            // unique violation
+            Some("23505") => Status::Conflict,
+            // value too long and invalid input syntax
+            Some("22001") | Some("22P02") => Status::BadRequest,
+            _ => Status::InternalServerError,
+        },
+        _ => Status::InternalServerError,
+    }
+}
+
 /// Check if the given field value is already present in the accounts table.
 pub async fn is_taken(
     field: &str,
@@ -118,12 +395,23 @@ pub async fn pictures(
     start: Option<i64>,
     end: Option<i64>,
     picture_id: Option<SqlxUuid>,
+    following: bool,
+    sort: Sort,
+    after: Option<&Cursor>,
 ) -> Option<Vec<Picture>> {
     let mut argc = 3;
-    let mut query = String::from("
+    let mut query = format!(
+        "
+		SELECT * FROM (
 		SELECT
+			{} AS sort_value,
 			pictures.picture_id, pictures.account_id,
-			pictures.superposable, pictures.creation_ts,
+			ARRAY(
+				SELECT superposable::text FROM picture_superposables
+				WHERE picture_superposables.picture_id = pictures.picture_id
+				ORDER BY layer
+			) AS superposables,
+			pictures.creation_ts,
 			accounts.username as author,
 			COUNT(CASE WHEN likes.value = TRUE THEN 1 END) AS like_count,
 			COUNT(CASE WHEN likes.value = FALSE THEN 1 END) AS dislike_count,
@@ -137,55 +425,109 @@ pub async fn pictures(
 			SELECT picture_id, COUNT(*) AS comment_count
 			FROM comments GROUP BY picture_id
 		) AS comment_counts ON pictures.picture_id = comment_counts.picture_id
-	");
+	",
+        sort.value()
+    );
+
+    // The first condition starts the WHERE clause, the next ones are added
+    let mut conditions = 0;
+    let mut clause = || {
+        conditions += 1;
+        if conditions == 1 {
+            "WHERE"
+        } else {
+            "AND"
+        }
+    };
+
+    if following {
+        query.push_str(&format!(
+            "{} pictures.account_id IN (
+				SELECT followed_id FROM follows WHERE follower_id = $1
+			)\n",
+            clause()
+        ));
+    }
 
     if let Some(_) = username {
         argc += 1;
-        query.push_str(&format!("WHERE accounts.username = ${}\n", argc));
+        query.push_str(&format!(
+            "{} accounts.username = ${}\n",
+            clause(),
+            argc
+        ));
     }
 
     if !superposable.is_empty() {
         argc += 1;
-        let clause = if argc == 4 { "WHERE" } else { "AND" };
         query.push_str(&format!(
-            "{} pictures.superposable = ANY(${}::superposable[])\n",
-            clause, argc
+            "{} pictures.picture_id IN (
+				SELECT picture_id FROM picture_superposables
+				WHERE superposable = ANY(${})
+			)\n",
+            clause(),
+            argc
         ));
     }
 
     if let Some(_start) = start {
         argc += 1;
-        let clause = if argc == 4 { "WHERE" } else { "AND" };
         query.push_str(&format!(
             "{} pictures.creation_ts >= to_timestamp(${})\n",
-            clause, argc
+            clause(),
+            argc
         ));
     }
 
     if let Some(_end) = end {
         argc += 1;
-        let clause = if argc == 4 { "WHERE" } else { "AND" };
         query.push_str(&format!(
             "{} pictures.creation_ts <= to_timestamp(${})\n",
-            clause, argc
+            clause(),
+            argc
         ));
     }
 
     if let Some(_picture_id) = picture_id {
         argc += 1;
-        let clause = if argc == 4 { "WHERE" } else { "AND" };
-        query
-            .push_str(&format!("{} pictures.picture_id = ${}\n", clause, argc));
+        query.push_str(&format!(
+            "{} pictures.picture_id = ${}\n",
+            clause(),
+            argc
+        ));
     }
 
     query.push_str(
         "
 		GROUP BY
 			pictures.picture_id, accounts.username, comment_counts.comment_count
-		ORDER BY creation_ts DESC LIMIT $2 OFFSET $3;
+		) AS gallery
 	",
     );
 
+    let (comparison, direction) = match sort.is_ascending() {
+        true => (">", "ASC"),
+        false => ("<", "DESC"),
+    };
+
+    if let Some(_after) = after {
+        argc += 2;
+        query.push_str(&format!(
+            "WHERE (gallery.sort_value, gallery.picture_id) {} (${}, ${})\n",
+            comparison,
+            argc - 1,
+            argc
+        ));
+    }
+
+    query.push_str(&format!(
+        "
+		ORDER BY gallery.sort_value {0}, gallery.picture_id {0}
+		LIMIT $2 OFFSET $3;
+	",
+        direction
+    ));
+
     let mut query = sqlx::query_as::<_, types::DbPicture>(&query)
         .bind(connected_user)
         .bind(count)
@@ -215,6 +557,10 @@ pub async fn pictures(
         query = query.bind(picture_id);
     }
 
+    if let Some(after) = after {
+        query = query.bind(after.value).bind(after.picture_id);
+    }
+
     let raw_pictures = query.fetch_all(&mut **db).await.unwrap_or(Vec::new());
     if raw_pictures.is_empty() {
         return None;
@@ -227,6 +573,83 @@ pub async fn pictures(
     Some(pictures)
 }
 
+/// Get the public profile of a user. The like, dislike and comment counts are
+/// the ones received on the user's pictures.
+pub async fn public_profile(
+    db: &mut Connection<PostgresDb>,
+    username: &str,
+) -> Option<PublicProfile> {
+    let query = "
+		SELECT
+			accounts.username,
+			accounts.creation_ts,
+			COALESCE(picture_counts.picture_count, 0) AS picture_count,
+			COALESCE(like_counts.like_count, 0) AS like_count,
+			COALESCE(like_counts.dislike_count, 0) AS dislike_count,
+			COALESCE(comment_counts.comment_count, 0) AS comment_count,
+			favorites.superposable AS favorite_superposable,
+			(
+				SELECT COUNT(*) FROM follows
+				WHERE follows.followed_id = accounts.account_id
+			) AS follower_count,
+			(
+				SELECT COUNT(*) FROM follows
+				WHERE follows.follower_id = accounts.account_id
+			) AS following_count
+		FROM accounts
+		LEFT JOIN (
+			SELECT account_id, COUNT(*) AS picture_count
+			FROM pictures GROUP BY account_id
+		) AS picture_counts ON accounts.account_id = picture_counts.account_id
+		LEFT JOIN (
+			SELECT
+				pictures.account_id,
+				COUNT(CASE WHEN likes.value = TRUE THEN 1 END) AS like_count,
+				COUNT(CASE WHEN likes.value = FALSE THEN 1 END) AS dislike_count
+			FROM likes
+			JOIN pictures ON likes.picture_id = pictures.picture_id
+			GROUP BY pictures.account_id
+		) AS like_counts ON accounts.account_id = like_counts.account_id
+		LEFT JOIN (
+			SELECT pictures.account_id, COUNT(*) AS comment_count
+			FROM comments
+			JOIN pictures ON comments.picture_id = pictures.picture_id
+			GROUP BY pictures.account_id
+		) AS comment_counts ON accounts.account_id = comment_counts.account_id
+		LEFT JOIN LATERAL (
+			SELECT picture_superposables.superposable
+			FROM picture_superposables
+			JOIN pictures
+				ON picture_superposables.picture_id = pictures.picture_id
+			WHERE pictures.account_id = accounts.account_id
+			GROUP BY picture_superposables.superposable
+			ORDER BY COUNT(*) DESC, MAX(pictures.creation_ts) DESC
+			LIMIT 1
+		) AS favorites ON TRUE
+		WHERE accounts.username = $1;
+	";
+
+    let raw_profile = sqlx::query_as::<_, types::DbPublicProfile>(query)
+        .bind(username)
+        .fetch_optional(&mut **db)
+        .await
+        .unwrap_or(None)?;
+
+    Some(PublicProfile {
+        username: raw_profile.username,
+        creation_ts: raw_profile.creation_ts.unix_timestamp(),
+        picture_count: raw_profile.picture_count,
+        like_count: raw_profile.like_count,
+        dislike_count: raw_profile.dislike_count,
+        comment_count: raw_profile.comment_count,
+        favorite_superposable: raw_profile
+            .favorite_superposable
+            .and_then(|superposable| superposable.parse().ok()),
+        follower_count: raw_profile.follower_count,
+        following_count: raw_profile.following_count,
+    })
+}
+
 /// Create an account for a new user.
 pub async fn create_account(
     db: &mut Connection<PostgresDb>,
@@ -300,7 +723,7 @@ pub async fn get_user_by_account_id(
 /// optional parameter. There should be an elegant way of bulding a multi
 /// type dynamic query but I did not find it. This is the simplest way.
 pub async fn put_user(
-    db: &mut Connection<PostgresDb>,
+    db: &mut PgConnection,
     account_id: &SqlxUuid,
     username: Option<String>,
     password: Option<String>,
@@ -312,7 +735,7 @@ pub async fn put_user(
         let result = sqlx::query(query)
             .bind(&username)
             .bind(account_id)
-            .execute(&mut **db)
+            .execute(&mut *db)
             .await
             .map_err(|_| ())?;
         if result.rows_affected() != 1 {
@@ -334,7 +757,7 @@ pub async fn put_user(
         let result = sqlx::query(query)
             .bind(&password_hash)
             .bind(account_id)
-            .execute(&mut **db)
+            .execute(&mut *db)
             .await
             .map_err(|_| ())?;
         if result.rows_affected() != 1 {
@@ -347,7 +770,7 @@ pub async fn put_user(
         let result = sqlx::query(query)
             .bind(&email)
             .bind(account_id)
-            .execute(&mut **db)
+            .execute(&mut *db)
             .await
             .map_err(|_| ())?;
         if result.rows_affected() != 1 {
@@ -360,7 +783,7 @@ pub async fn put_user(
         let result = sqlx::query(query)
             .bind(&email_notifications)
             .bind(account_id)
-            .execute(&mut **db)
+            .execute(&mut *db)
             .await
             .map_err(|_| ())?;
         if result.rows_affected() != 1 {
@@ -371,24 +794,121 @@ pub async fn put_user(
     Ok(())
 }
 
-/// Add a like or a dislike on a given picture
-pub async fn put_like(
+/// Enable two-factor authentication with the given secret and replace the
+/// recovery codes of the user. Disable it when no secret is given. The time
+/// step of the code used for the enrollment is recorded as already used.
+pub async fn set_two_factor(
     db: &mut Connection<PostgresDb>,
-    like: bool,
-    picture_id: &SqlxUuid,
     account_id: &SqlxUuid,
-) -> Result<(), ()> {
+    totp: Option<(&str, u64)>,
+    recovery_code_hashes: &[String],
+) -> Result<(), sqlx::Error> {
+    let mut tx = (&mut **db).begin().await?;
+
     let query = "
-		INSERT INTO likes (picture_id, account_id, value)
-		VALUES ($1, $2, $3)
-		ON CONFLICT ON CONSTRAINT no_duplicate_like
-		DO UPDATE SET value = $3;
+		UPDATE accounts SET totp_secret = $1, totp_last_step = $2
+		WHERE account_id = $3;
 	";
+    sqlx::query(query)
+        .bind(totp.map(|(secret, _)| secret))
+        .bind(totp.map(|(_, step)| step as i64))
+        .bind(account_id)
+        .execute(&mut tx)
+        .await?;
+
+    sqlx::query("DELETE FROM recovery_codes WHERE account_id = $1")
+        .bind(account_id)
+        .execute(&mut tx)
+        .await?;
+
+    for code_hash in recovery_code_hashes {
+        sqlx::query(
+            "INSERT INTO recovery_codes (account_id, code_hash) VALUES ($1, $2)",
+        )
+        .bind(account_id)
+        .bind(code_hash)
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Delete an account and everything that belongs to it. Returns the ids of
+/// the deleted pictures so that their files can be removed.
+pub async fn delete_account(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+) -> Result<Vec<SqlxUuid>, sqlx::Error> {
+    let mut tx = (&mut **db).begin().await?;
+
+    let picture_ids = sqlx::query_scalar::<_, SqlxUuid>(
+        "SELECT picture_id FROM pictures WHERE account_id = $1 FOR UPDATE",
+    )
+    .bind(account_id)
+    .fetch_all(&mut tx)
+    .await?;
+
+    // likes, comments and pictures of the account are deleted in cascade
+    let result = sqlx::query("DELETE FROM accounts WHERE account_id = $1")
+        .bind(account_id)
+        .execute(&mut tx)
+        .await?;
+    if result.rows_affected() != 1 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    tx.commit().await?;
+    Ok(picture_ids)
+}
+
+/// Record the time step of an accepted two-factor code. Returns false if a code
+/// of this step or of a later one has already been used.
+pub async fn use_totp_step(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+    step: u64,
+) -> Result<bool, sqlx::Error> {
+    let query = "
+		UPDATE accounts SET totp_last_step = $1
+		WHERE account_id = $2
+			AND (totp_last_step IS NULL OR totp_last_step < $1);
+	";
+
+    let result = sqlx::query(query)
+        .bind(step as i64)
+        .bind(account_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Get the recovery code hashes of the user
+pub async fn recovery_codes(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+) -> Vec<String> {
+    let query = "SELECT code_hash FROM recovery_codes WHERE account_id = $1";
+
+    sqlx::query_scalar::<_, String>(query)
+        .bind(account_id)
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default()
+}
+
+/// Consume a recovery code
+pub async fn delete_recovery_code(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+    code_hash: &str,
+) -> Result<(), ()> {
+    let query =
+        "DELETE FROM recovery_codes WHERE account_id = $1 AND code_hash = $2";
 
     match sqlx::query(query)
-        .bind(picture_id)
         .bind(account_id)
-        .bind(like)
+        .bind(code_hash)
         .execute(&mut **db)
         .await
         .map_err(|_| ())?
@@ -398,6 +918,66 @@ pub async fn put_like(
     }
 }
 
+/// Add a like or a dislike on a given picture. Returns true if the like is new
+/// or if its value has changed.
+pub async fn put_like(
+    db: &mut Connection<PostgresDb>,
+    like: bool,
+    picture_id: &SqlxUuid,
+    account_id: &SqlxUuid,
+) -> Result<bool, ()> {
+    let query = "
+		WITH old_like AS (
+			SELECT value FROM likes WHERE picture_id = $1 AND account_id = $2
+		), new_like AS (
+			INSERT INTO likes (picture_id, account_id, value)
+			VALUES ($1, $2, $3)
+			ON CONFLICT ON CONSTRAINT no_duplicate_like
+			DO UPDATE SET
+				value = $3,
+				creation_ts = CASE WHEN likes.value = $3
+					THEN likes.creation_ts ELSE NOW()
+				END
+			RETURNING value
+		)
+		SELECT (SELECT value FROM old_like) IS DISTINCT FROM new_like.value
+		FROM new_like;
+	";
+
+    sqlx::query_scalar::<_, bool>(query)
+        .bind(picture_id)
+        .bind(account_id)
+        .bind(like)
+        .fetch_one(&mut **db)
+        .await
+        .map_err(|_| ())
+}
+
+/// Count the likes and dislikes of a given picture
+pub async fn like_count(
+    db: &mut Connection<PostgresDb>,
+    picture_id: &SqlxUuid,
+) -> Option<LikeCount> {
+    let query = "
+		SELECT
+			COUNT(*) FILTER (WHERE value = TRUE) AS like_count,
+			COUNT(*) FILTER (WHERE value = FALSE) AS dislike_count
+		FROM likes
+		WHERE picture_id = $1;
+	";
+
+    let (like_count, dislike_count) = sqlx::query_as::<_, (i64, i64)>(query)
+        .bind(picture_id)
+        .fetch_one(&mut **db)
+        .await
+        .ok()?;
+    Some(LikeCount {
+        picture_id: from_sqlx_to_serde(picture_id),
+        like_count,
+        dislike_count,
+    })
+}
+
 /// Remove like or dislike on a given picture
 pub async fn delete_like(
     db: &mut Connection<PostgresDb>,
@@ -418,23 +998,71 @@ pub async fn delete_like(
     }
 }
 
+/// Follow a user
+pub async fn put_follow(
+    db: &mut Connection<PostgresDb>,
+    follower_id: &SqlxUuid,
+    followed_id: &SqlxUuid,
+) -> Result<(), ()> {
+    let query = "
+		INSERT INTO follows (follower_id, followed_id)
+		VALUES ($1, $2)
+		ON CONFLICT ON CONSTRAINT no_duplicate_follow DO NOTHING;
+	";
+
+    sqlx::query(query)
+        .bind(follower_id)
+        .bind(followed_id)
+        .execute(&mut **db)
+        .await
+        .map(|_| ())
+        .map_err(|_| ())
+}
+
+/// Unfollow a user
+pub async fn delete_follow(
+    db: &mut Connection<PostgresDb>,
+    follower_id: &SqlxUuid,
+    followed_id: &SqlxUuid,
+) -> Result<u64, sqlx::Error> {
+    let query =
+        "DELETE FROM follows WHERE follower_id = $1 AND followed_id = $2";
+
+    sqlx::query(query)
+        .bind(follower_id)
+        .bind(followed_id)
+        .execute(&mut **db)
+        .await
+        .map(|result| result.rows_affected())
+}
+
 /// Add given comment to a picture
 pub async fn comment(
     db: &mut Connection<PostgresDb>,
     comment: &str,
     picture_id: &SqlxUuid,
     account_id: &SqlxUuid,
+    parent_comment_id: Option<&SqlxUuid>,
 ) -> Result<Comment, sqlx::Error> {
+    // replies must be on the same picture as their parent comment
     let query = "
 		WITH new_comment AS (
-			INSERT INTO comments (picture_id, account_id, content)
-			VALUES ($1, $2, $3)
+			INSERT INTO comments
+				(picture_id, account_id, content, parent_comment_id)
+			SELECT $1, $2, $3, $4
+			WHERE $4::uuid IS NULL OR EXISTS (
+				SELECT 1 FROM comments
+				WHERE comment_id = $4 AND picture_id = $1
+			)
 			RETURNING *
 		)
 		SELECT
+			new_comment.comment_id,
+			new_comment.parent_comment_id,
 			new_comment.picture_id,
 			new_comment.account_id,
 			new_comment.creation_ts,
+			new_comment.edited_ts,
 			new_comment.content,
 			accounts.username AS author
 		FROM new_comment
@@ -445,53 +1073,231 @@ pub async fn comment(
         .bind(picture_id)
         .bind(account_id)
         .bind(comment)
+        .bind(parent_comment_id)
         .fetch_one(&mut **db)
         .await?;
 
-    Ok(Comment {
-        picture_id: from_sqlx_to_serde(&new_comment.picture_id),
-        account_id: from_sqlx_to_serde(&new_comment.account_id),
-        creation_ts: new_comment.creation_ts.unix_timestamp(),
-        content: new_comment.content,
-        author: new_comment.author,
-    })
+    Ok(Comment::from(&new_comment))
 }
 
-/// Create a new picture
+/// Edit a comment. Returns None if the comment does not exist or if it was
+/// not written by the given user.
+pub async fn put_comment(
+    db: &mut Connection<PostgresDb>,
+    comment: &str,
+    comment_id: &SqlxUuid,
+    account_id: &SqlxUuid,
+) -> Result<Option<Comment>, sqlx::Error> {
+    let query = "
+		WITH edited_comment AS (
+			UPDATE comments SET content = $3, edited_ts = NOW()
+			WHERE comment_id = $1 AND account_id = $2
+			RETURNING *
+		)
+		SELECT
+			edited_comment.comment_id,
+			edited_comment.parent_comment_id,
+			edited_comment.picture_id,
+			edited_comment.account_id,
+			edited_comment.creation_ts,
+			edited_comment.edited_ts,
+			edited_comment.content,
+			accounts.username AS author
+		FROM edited_comment
+		JOIN accounts ON edited_comment.account_id = accounts.account_id;
+	";
+
+    let edited_comment = sqlx::query_as::<_, types::DbComment>(query)
+        .bind(comment_id)
+        .bind(account_id)
+        .bind(comment)
+        .fetch_optional(&mut **db)
+        .await?;
+
+    Ok(edited_comment.as_ref().map(Comment::from))
+}
+
+/// Check if a comment exists, whoever its author is
+pub async fn comment_exists(
+    db: &mut Connection<PostgresDb>,
+    comment_id: &SqlxUuid,
+) -> Result<bool, sqlx::Error> {
+    let query = "SELECT EXISTS(SELECT 1 FROM comments WHERE comment_id = $1);";
+
+    sqlx::query_scalar::<_, bool>(query)
+        .bind(comment_id)
+        .fetch_one(&mut **db)
+        .await
+}
+
+/// Delete a comment written by the given user or on one of their pictures
+pub async fn delete_comment(
+    db: &mut Connection<PostgresDb>,
+    comment_id: &SqlxUuid,
+    account_id: &SqlxUuid,
+) -> Result<u64, sqlx::Error> {
+    let query = "
+		DELETE FROM comments
+		WHERE comment_id = $1 AND (
+			account_id = $2 OR picture_id IN (
+				SELECT picture_id FROM pictures WHERE account_id = $2
+			)
+		);
+	";
+
+    sqlx::query(query)
+        .bind(comment_id)
+        .bind(account_id)
+        .execute(&mut **db)
+        .await
+        .map(|result| result.rows_affected())
+}
+
+/// Create a new picture with its superposables
 pub async fn post_picture(
     db: &mut Connection<PostgresDb>,
     account_id: &SqlxUuid,
-    superposable: Superposable,
+    layers: &[PlacedLayer],
 ) -> Result<Picture, sqlx::Error> {
     let query = "
 		WITH new_picture AS (
-			INSERT INTO pictures (account_id, superposable)
-			VALUES ($1, $2)
+			INSERT INTO pictures (account_id)
+			VALUES ($1)
 			RETURNING *
+		), new_layers AS (
+			INSERT INTO picture_superposables
+				(picture_id, layer, superposable, x, y, scale, rotation)
+			SELECT
+				new_picture.picture_id,
+				layers.layer - 1,
+				layers.superposable,
+				layers.x,
+				layers.y,
+				layers.scale,
+				layers.rotation
+			FROM new_picture, UNNEST(
+				$2::varchar[], $3::int4[], $4::int4[], $5::float8[], $6::float8[]
+			) WITH ORDINALITY AS layers(superposable, x, y, scale, rotation, layer)
 		)
 		SELECT
 			new_picture.picture_id,
 			new_picture.account_id,
-			new_picture.superposable,
+			$2::text[] AS superposables,
 			new_picture.creation_ts,
 			accounts.username AS author,
 			0::INT8 AS like_count,
 			0::INT8 AS dislike_count,
 			0::INT8 AS comment_count,
 			FALSE::BOOL AS liked,
-			FALSE::BOOL AS disliked
+			FALSE::BOOL AS disliked,
+			EXTRACT(EPOCH FROM new_picture.creation_ts)::float8 AS sort_value
 		FROM new_picture
 		JOIN accounts ON new_picture.account_id = accounts.account_id;
 	";
 
     let new_picture = sqlx::query_as::<_, types::DbPicture>(query)
         .bind(account_id)
-        .bind(superposable)
+        .bind(
+            layers
+                .iter()
+                .map(|layer| layer.superposable.as_ref())
+                .collect::<Vec<&str>>(),
+        )
+        .bind(
+            layers
+                .iter()
+                .map(|layer| layer.placement.x as i32)
+                .collect::<Vec<i32>>(),
+        )
+        .bind(
+            layers
+                .iter()
+                .map(|layer| layer.placement.y.unwrap_or(0) as i32)
+                .collect::<Vec<i32>>(),
+        )
+        .bind(
+            layers
+                .iter()
+                .map(|layer| layer.placement.scale)
+                .collect::<Vec<f64>>(),
+        )
+        .bind(
+            layers
+                .iter()
+                .map(|layer| layer.placement.rotation)
+                .collect::<Vec<f64>>(),
+        )
         .fetch_one(&mut **db)
         .await?;
     Ok(Picture::from(&new_picture))
 }
 
+/// Get the enabled superposables of the catalog, every one of them or only the
+/// ones with the given names
+pub async fn superposables(
+    db: &mut Connection<PostgresDb>,
+    names: Option<&[Superposable]>,
+) -> Vec<types::DbSuperposable> {
+    let query = "
+		SELECT name, file, category, x, y, scale, rotation
+		FROM superposables
+		WHERE enabled = TRUE
+		AND ($1::varchar[] IS NULL OR name = ANY($1))
+		ORDER BY category, name;
+	";
+
+    sqlx::query_as::<_, types::DbSuperposable>(query)
+        .bind(names.map(|names| {
+            names
+                .iter()
+                .map(|name| name.as_ref())
+                .collect::<Vec<&str>>()
+        }))
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default()
+}
+
+/// Add a superposable to the catalog
+pub async fn post_superposable(
+    db: &mut Connection<PostgresDb>,
+    name: &Superposable,
+    file: &str,
+    category: &str,
+    placement: &Placement,
+) -> Result<(), sqlx::Error> {
+    let query = "
+		INSERT INTO superposables (name, file, category, x, y, scale, rotation)
+		VALUES ($1, $2, $3, $4, $5, $6, $7);
+	";
+
+    sqlx::query(query)
+        .bind(name.as_ref())
+        .bind(file)
+        .bind(category)
+        .bind(placement.x as i32)
+        .bind(placement.y.map(|y| y as i32))
+        .bind(placement.scale)
+        .bind(placement.rotation)
+        .execute(&mut **db)
+        .await
+        .map(|_| ())
+}
+
+/// Remove a superposable from the catalog
+pub async fn delete_superposable(
+    db: &mut Connection<PostgresDb>,
+    name: &Superposable,
+) -> Result<(), sqlx::Error> {
+    let query = "DELETE FROM superposables WHERE name = $1";
+
+    sqlx::query(query)
+        .bind(name.as_ref())
+        .execute(&mut **db)
+        .await
+        .map(|_| ())
+}
+
 /// Delete a picture
 pub async fn delete_picture(
     db: &mut Connection<PostgresDb>,
@@ -509,57 +1315,321 @@ pub async fn delete_picture(
         .map(|result| result.rows_affected())
 }
 
-/// Get comments for a given picture
+/// Get comments for a given picture as a thread. Each comment is followed by
+/// its replies and the comments of the same level are sorted from the oldest.
+/// Every comment is returned if no count is given.
 pub async fn comments(
     db: &mut Connection<PostgresDb>,
     picture_id: &SqlxUuid,
+    index: u32,
+    count: Option<u32>,
 ) -> Option<Vec<Comment>> {
+    let query = "
+		WITH RECURSIVE thread AS (
+			SELECT
+				comments.*,
+				0 AS depth,
+				ARRAY[
+					TO_CHAR(comments.creation_ts AT TIME ZONE 'UTC', 'YYYYMMDDHH24MISSUS')
+					|| comments.comment_id::text
+				] AS path
+			FROM comments
+			WHERE comments.picture_id = $1
+			AND comments.parent_comment_id IS NULL
+			UNION ALL
+			SELECT
+				comments.*,
+				thread.depth + 1,
+				thread.path || (
+					TO_CHAR(comments.creation_ts AT TIME ZONE 'UTC', 'YYYYMMDDHH24MISSUS')
+					|| comments.comment_id::text
+				)
+			FROM comments
+			JOIN thread ON comments.parent_comment_id = thread.comment_id
+		)
+		SELECT
+			thread.comment_id,
+			thread.parent_comment_id,
+			thread.picture_id,
+			thread.account_id,
+			thread.creation_ts,
+			thread.edited_ts,
+			thread.content,
+			thread.depth,
+			accounts.username as author
+		FROM thread
+		JOIN accounts ON thread.account_id = accounts.account_id
+		ORDER BY thread.path
+		LIMIT $2 OFFSET $3;
+	";
+
+    let raw_comments = sqlx::query_as::<_, types::DbComment>(query)
+        .bind(picture_id)
+        .bind(count.map(i64::from))
+        .bind(i64::from(index) * i64::from(count.unwrap_or(0)))
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default();
+
+    let comments = raw_comments.iter().map(Comment::from).collect();
+    Some(comments)
+}
+
+/// Get every comment written by a given user
+pub async fn user_comments(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+) -> Vec<Comment> {
     let query = "
 		SELECT
+			comments.comment_id,
+			comments.parent_comment_id,
 			comments.picture_id,
 			comments.account_id,
 			comments.creation_ts,
+			comments.edited_ts,
 			comments.content,
 			accounts.username as author
 		FROM comments
 		JOIN accounts ON comments.account_id = accounts.account_id
-		WHERE comments.picture_id = $1
+		WHERE comments.account_id = $1
 		ORDER BY creation_ts ASC;
 	";
 
-    let raw_comments = sqlx::query_as::<_, types::DbComment>(query)
-        .bind(picture_id)
+    sqlx::query_as::<_, types::DbComment>(query)
+        .bind(account_id)
         .fetch_all(&mut **db)
         .await
-        .unwrap_or_default();
+        .unwrap_or_default()
+        .iter()
+        .map(Comment::from)
+        .collect()
+}
 
-    let comments = raw_comments
+/// Get the likes and dislikes given by a user, most recent first. Only the
+/// likes or the dislikes are returned when a value is given, and every one of
+/// them when no count is given.
+pub async fn user_likes(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+    value: Option<bool>,
+    index: u32,
+    count: Option<u32>,
+) -> Vec<Like> {
+    let query = "
+		SELECT picture_id, value, creation_ts FROM likes
+		WHERE account_id = $1
+		AND ($2::boolean IS NULL OR value = $2)
+		ORDER BY creation_ts DESC, picture_id
+		LIMIT $3 OFFSET $4;
+	";
+
+    sqlx::query_as::<_, types::DbLike>(query)
+        .bind(account_id)
+        .bind(value)
+        .bind(count.map(i64::from))
+        .bind(i64::from(index) * i64::from(count.unwrap_or(0)))
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default()
         .iter()
-        .map(|raw_comment| Comment {
-            picture_id: from_sqlx_to_serde(&raw_comment.picture_id),
-            account_id: from_sqlx_to_serde(&raw_comment.account_id),
-            creation_ts: raw_comment.creation_ts.unix_timestamp(),
-            content: raw_comment.content.clone(),
-            author: raw_comment.author.clone(),
+        .map(|raw_like| Like {
+            picture_id: from_sqlx_to_serde(&raw_like.picture_id),
+            value: raw_like.value,
+            creation_ts: raw_like.creation_ts.unix_timestamp(),
         })
-        .collect();
-    Some(comments)
+        .collect()
 }
 
-/// Get email if picture author has email notifications enabled
-pub async fn has_email_notifications(
+/// Get the users who liked or disliked a picture, most recent first. Only the
+/// likes or the dislikes are returned when a value is given, and every one of
+/// them when no count is given.
+pub async fn picture_likes(
     db: &mut Connection<PostgresDb>,
     picture_id: &SqlxUuid,
-) -> Option<String> {
+    value: Option<bool>,
+    index: u32,
+    count: Option<u32>,
+) -> Vec<Liker> {
     let query = "
-		SELECT email FROM accounts
-		JOIN pictures ON accounts.account_id = pictures.account_id
-		WHERE pictures.picture_id = $1 AND accounts.email_notifications = TRUE;
+		SELECT accounts.username, likes.value, likes.creation_ts
+		FROM likes
+		JOIN accounts ON likes.account_id = accounts.account_id
+		WHERE likes.picture_id = $1
+		AND ($2::boolean IS NULL OR likes.value = $2)
+		ORDER BY likes.creation_ts DESC, accounts.username
+		LIMIT $3 OFFSET $4;
+	";
+
+    sqlx::query_as::<_, types::DbLiker>(query)
+        .bind(picture_id)
+        .bind(value)
+        .bind(count.map(i64::from))
+        .bind(i64::from(index) * i64::from(count.unwrap_or(0)))
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|raw_liker| Liker {
+            username: raw_liker.username.clone(),
+            value: raw_liker.value,
+            creation_ts: raw_liker.creation_ts.unix_timestamp(),
+        })
+        .collect()
+}
+
+/// Notify of an event on a picture. The picture author is notified unless
+/// usernames are given, in which case these users are notified instead. The
+/// author of the event is never notified and users who disabled this kind of
+/// notification are ignored. Returns the emails of the notified users with
+/// email notifications enabled.
+pub async fn notify(
+    db: &mut Connection<PostgresDb>,
+    kind: Kind,
+    author_id: &SqlxUuid,
+    picture_id: &SqlxUuid,
+    usernames: Option<&[String]>,
+) -> Vec<String> {
+    let query = "
+		WITH recipients AS (
+			SELECT accounts.account_id, accounts.email, accounts.email_notifications
+			FROM accounts
+			WHERE accounts.account_id <> $2
+			AND CASE $1
+				WHEN 'comment' THEN accounts.comment_notifications
+				WHEN 'like' THEN accounts.like_notifications
+				WHEN 'mention' THEN accounts.mention_notifications
+			END
+			AND CASE WHEN $4::varchar[] IS NULL
+				THEN accounts.account_id = (
+					SELECT account_id FROM pictures WHERE picture_id = $3
+				)
+				ELSE accounts.username = ANY($4)
+			END
+		), new_notifications AS (
+			INSERT INTO notifications (account_id, author_id, picture_id, kind)
+			SELECT account_id, $2, $3, $1 FROM recipients
+			ON CONFLICT DO NOTHING
+		)
+		SELECT email FROM recipients WHERE email_notifications = TRUE;
 	";
 
     sqlx::query_scalar::<_, String>(query)
+        .bind(kind)
+        .bind(author_id)
         .bind(picture_id)
-        .fetch_optional(&mut **db)
+        .bind(usernames)
+        .fetch_all(&mut **db)
         .await
         .unwrap_or_default()
 }
+
+/// Get the notifications of a user, most recent first
+pub async fn notifications(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+    index: u32,
+    count: u32,
+) -> Vec<Notification> {
+    let query = "
+		SELECT
+			notifications.notification_id,
+			notifications.kind,
+			accounts.username AS author,
+			notifications.picture_id,
+			notifications.creation_ts,
+			notifications.read
+		FROM notifications
+		JOIN accounts ON notifications.author_id = accounts.account_id
+		WHERE notifications.account_id = $1
+		ORDER BY notifications.creation_ts DESC, notifications.notification_id
+		LIMIT $2 OFFSET $3;
+	";
+
+    sqlx::query_as::<_, types::DbNotification>(query)
+        .bind(account_id)
+        .bind(i64::from(count))
+        .bind(i64::from(index) * i64::from(count))
+        .fetch_all(&mut **db)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|raw_notification| Notification {
+            notification_id: from_sqlx_to_serde(
+                &raw_notification.notification_id,
+            ),
+            kind: raw_notification.kind,
+            author: raw_notification.author.clone(),
+            picture_id: from_sqlx_to_serde(&raw_notification.picture_id),
+            creation_ts: raw_notification.creation_ts.unix_timestamp(),
+            read: raw_notification.read,
+        })
+        .collect()
+}
+
+/// Count the unread notifications of a user
+pub async fn unread_notifications(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+) -> i64 {
+    let query = "
+		SELECT COUNT(*) FROM notifications
+		WHERE account_id = $1 AND read = FALSE;
+	";
+
+    sqlx::query_scalar::<_, i64>(query)
+        .bind(account_id)
+        .fetch_one(&mut **db)
+        .await
+        .unwrap_or_default()
+}
+
+/// Mark notifications of a user as read, every one of them if no ids are given
+pub async fn read_notifications(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+    notification_ids: Option<&[SqlxUuid]>,
+) -> Result<u64, sqlx::Error> {
+    let query = "
+		UPDATE notifications SET read = TRUE
+		WHERE account_id = $1 AND read = FALSE
+		AND ($2::uuid[] IS NULL OR notification_id = ANY($2));
+	";
+
+    sqlx::query(query)
+        .bind(account_id)
+        .bind(notification_ids)
+        .execute(&mut **db)
+        .await
+        .map(|result| result.rows_affected())
+}
+
+/// Set the notification preferences of a user, None values are left unchanged
+pub async fn put_notification_preferences(
+    db: &mut PgConnection,
+    account_id: &SqlxUuid,
+    comment_notifications: Option<bool>,
+    like_notifications: Option<bool>,
+    mention_notifications: Option<bool>,
+) -> Result<(), ()> {
+    let query = "
+		UPDATE accounts SET
+			comment_notifications = COALESCE($2, comment_notifications),
+			like_notifications = COALESCE($3, like_notifications),
+			mention_notifications = COALESCE($4, mention_notifications)
+		WHERE account_id = $1;
+	";
+
+    match sqlx::query(query)
+        .bind(account_id)
+        .bind(comment_notifications)
+        .bind(like_notifications)
+        .bind(mention_notifications)
+        .execute(&mut *db)
+        .await
+        .map_err(|_| ())?
+    {
+        ref result if result.rows_affected() != 1 => Err(()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,236 @@
+//! Limit the number of attempts on sensitive routes.
+//!
+//! Attempts are counted per route, for the client ip and optionally for an
+//! identifier given by the route handler (like a username or an email). When
+//! the maximum number of attempts is reached within the configured window the
+//! client or identifier is locked out of the route for a while. Locked requests
+//! get a `429 Too Many Requests` response with a `Retry-After` header.
+//!
+//! The counters are kept in a [`Cache`] so they are shared between api
+//! instances when using a persistent storage backend.
+
+use crate::cache::{self, Cache};
+use crate::config;
+use crate::result::ApiResult;
+use data_encoding::HEXLOWER;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::time::OffsetDateTime;
+use rocket::{Response, State};
+use sha1::{Digest, Sha1};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Attempts counter stored in the cache.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Attempts {
+    /// number of attempts in the current window
+    count: u32,
+    /// timestamp of the first attempt of the current window
+    window_start: i64,
+    /// timestamp until which the key is locked out
+    locked_until: i64,
+}
+
+/// Time in seconds to wait before retrying a locked request. This is stored in
+/// the request local cache to be used by the [`RateLimit`] fairing.
+#[derive(Default)]
+struct RetryAfter(AtomicU64);
+
+/// Reason why an attempt is refused.
+#[derive(Debug)]
+pub enum Refusal {
+    /// Locked out for the given time in seconds.
+    Locked(u64),
+    /// The attempts could not be read from the cache.
+    Storage(cache::Error),
+}
+
+impl From<cache::Error> for Refusal {
+    fn from(error: cache::Error) -> Self {
+        Refusal::Storage(error)
+    }
+}
+
+impl<T: Serialize> From<Refusal> for ApiResult<T> {
+    fn from(refusal: Refusal) -> Self {
+        match refusal {
+            Refusal::Locked(retry_after) => ApiResult::Failure {
+                status: Status::TooManyRequests,
+                message: message(retry_after),
+            },
+            Refusal::Storage(error) => error.into(),
+        }
+    }
+}
+
+/// Current unix timestamp.
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+/// Limit the attempts on the current route. This request guard fails with a
+/// `429 Too Many Requests` status when the client ip is locked out.
+pub struct Limiter<'r> {
+    attempts: &'r Cache<Attempts>,
+    retry_after: &'r RetryAfter,
+    scope: String,
+    ip: String,
+}
+
+impl<'r> Limiter<'r> {
+    /// Cache key for the given kind of counter. The value is hashed so that
+    /// the key always fits in the cache whatever the length of the value.
+    fn key(&self, kind: &str, value: &str) -> String {
+        let hash = HEXLOWER.encode(&Sha1::digest(value.as_bytes()));
+        format!("{}:{}:{}", self.scope, kind, hash)
+    }
+
+    /// Check if the key is locked out. Returns the time left in seconds.
+    async fn locked(&self, key: &str) -> Result<(), Refusal> {
+        let attempts = match self.attempts.get(key).await? {
+            Some(attempts) => attempts,
+            None => return Ok(()),
+        };
+        let left = attempts.locked_until - now();
+        if left > 0 {
+            self.retry_after.0.store(left as u64, Ordering::Relaxed);
+            Err(Refusal::Locked(left as u64))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Count an attempt for the given key and lock it out if the maximum
+    /// number of attempts has been reached.
+    async fn count(&self, key: &str) -> Result<(), cache::Error> {
+        let now = now();
+        let window = *config::RATE_LIMIT_WINDOW as i64;
+        let mut attempts = match self.attempts.get(key).await? {
+            Some(attempts) if now - attempts.window_start < window => attempts,
+            _ => Attempts {
+                count: 0,
+                window_start: now,
+                locked_until: 0,
+            },
+        };
+        attempts.count += 1;
+        if attempts.count >= *config::RATE_LIMIT_ATTEMPTS {
+            attempts.count = 0;
+            attempts.window_start = now;
+            attempts.locked_until = now + *config::RATE_LIMIT_LOCKOUT as i64;
+        }
+        let lifetime = Duration::from_secs(
+            (*config::RATE_LIMIT_WINDOW).max(*config::RATE_LIMIT_LOCKOUT),
+        );
+        self.attempts.set(key, &attempts, lifetime).await?;
+        Ok(())
+    }
+
+    /// Check that the given identifier is not locked out of the route. The
+    /// attempt is refused when it is or when the attempts cannot be read.
+    pub async fn check(&self, identifier: &str) -> Result<(), Refusal> {
+        self.locked(&self.key("id", identifier)).await
+    }
+
+    /// Count an attempt for the client ip and the given identifier. Since the
+    /// locks are checked before each attempt, a counter that could not be
+    /// stored is only logged.
+    pub async fn hit(&self, identifier: Option<&str>) {
+        let mut counted = self.count(&self.key("ip", &self.ip)).await;
+        if let Some(identifier) = identifier {
+            counted =
+                counted.and(self.count(&self.key("id", identifier)).await);
+        }
+        if let Err(error) = counted {
+            error!("failed to count attempt: {}", error);
+        }
+    }
+
+    /// Forget the attempts of the given identifier.
+    pub async fn reset(&self, identifier: &str) {
+        if let Err(error) = self.attempts.del(&self.key("id", identifier)).await
+        {
+            error!("failed to reset attempts: {}", error);
+        }
+    }
+}
+
+/// Error message for locked out requests.
+fn message(retry_after: u64) -> String {
+    format!("too many attempts, retry in {} seconds", retry_after)
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Locked,
+    Storage,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Limiter<'r> {
+    type Error = Error;
+
+    async fn from_request(
+        request: &'r Request<'_>,
+    ) -> Outcome<Self, Self::Error> {
+        let attempts = request
+            .guard::<&State<Cache<Attempts>>>()
+            .await
+            .expect("Failed to get rate limit cache");
+        let scope = match request.route() {
+            Some(route) => format!("{}:{}", route.method, route.uri),
+            None => request.uri().path().to_string(),
+        };
+        let limiter = Limiter {
+            attempts,
+            retry_after: request.local_cache(RetryAfter::default),
+            scope,
+            ip: request
+                .client_ip()
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+        };
+        match limiter.locked(&limiter.key("ip", &limiter.ip)).await {
+            Ok(()) => Outcome::Success(limiter),
+            Err(Refusal::Locked(_)) => {
+                Outcome::Failure((Status::TooManyRequests, Error::Locked))
+            }
+            Err(Refusal::Storage(error)) => {
+                error!("failed to check rate limit: {}", error);
+                Outcome::Failure((Status::InternalServerError, Error::Storage))
+            }
+        }
+    }
+}
+
+/// Add the `Retry-After` header to the locked out responses.
+pub struct RateLimit;
+
+#[rocket::async_trait]
+impl Fairing for RateLimit {
+    fn info(&self) -> Info {
+        Info {
+            name: "Attaching Retry-After header to rate limited responses",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(
+        &self,
+        request: &'r Request<'_>,
+        response: &mut Response<'r>,
+    ) {
+        let retry_after = request.local_cache(RetryAfter::default);
+        let retry_after = retry_after.0.load(Ordering::Relaxed);
+        if response.status() == Status::TooManyRequests && retry_after > 0 {
+            response.set_header(Header::new(
+                "Retry-After",
+                retry_after.to_string(),
+            ));
+        }
+    }
+}
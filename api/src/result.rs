@@ -1,5 +1,6 @@
 //! Build `Json` responses for the api.
 
+use crate::cache;
 use rocket::http::{ContentType, Method, Status};
 use rocket::serde::{json::Json, Serialize};
 use rocket::{
@@ -72,6 +73,18 @@ pub enum ApiResult<T: Serialize> {
     Failure { status: Status, message: String },
 }
 
+/// A value could not be read from or written to the cache. Routes return this
+/// instead of reporting a success with a lost session or token.
+impl<T: Serialize> From<cache::Error> for ApiResult<T> {
+    fn from(error: cache::Error) -> Self {
+        error!("{}", error);
+        ApiResult::Failure {
+            status: Status::InternalServerError,
+            message: String::from("failed to access the cache storage"),
+        }
+    }
+}
+
 fn build_success_response<'r, T: Serialize>(
     status: Status,
     payload: T,
@@ -178,6 +191,16 @@ pub fn unprocessable_entity(req: &Request) -> Json<ApiError> {
     ))
 }
 
+#[catch(429)]
+pub fn too_many_requests(req: &Request) -> Json<ApiError> {
+    Json(ApiError::new(
+        Status::TooManyRequests,
+        "too many attempts, retry later",
+        req.method(),
+        &req.uri().path().to_string(),
+    ))
+}
+
 #[catch(500)]
 pub fn internal_error(req: &Request) -> Json<ApiError> {
     Json(ApiError::new(
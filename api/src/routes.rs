@@ -1,8 +1,10 @@
 //! Every api route handler.
 
+pub mod events;
 pub mod picture;
 pub mod pictures;
 pub mod user;
+pub mod users;
 
 /// CORS preflight handler.
 #[options("/<_..>")]
@@ -0,0 +1,46 @@
+use crate::events::{Event, Events};
+use rocket::response::stream::{Event as StreamEvent, EventStream};
+use rocket::serde::uuid::Uuid;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Shutdown, State};
+
+/// Convert an event to its server-sent representation.
+fn stream_event(event: &Event) -> StreamEvent {
+    let data = match event {
+        Event::Picture(picture) => StreamEvent::json(picture),
+        Event::Comment(comment) => StreamEvent::json(comment),
+        Event::Likes(likes) => StreamEvent::json(likes),
+    };
+    data.event(event.name())
+}
+
+/// Stream the new pictures and the like count changes as server-sent events.
+/// The new comments are only sent for the pictures given as parameters, which
+/// should be the ones the client is viewing.
+#[get("/?<picture>")]
+pub async fn get(
+    picture: Vec<Uuid>,
+    events: &State<Events>,
+    mut end: Shutdown,
+) -> EventStream![] {
+    let mut receiver = events.subscribe();
+    EventStream! {
+        loop {
+            let event = select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut end => break,
+            };
+            if let Event::Comment(_) = event {
+                if !picture.contains(&event.picture_id()) {
+                    continue;
+                }
+            }
+            yield stream_event(&event);
+        }
+    }
+}
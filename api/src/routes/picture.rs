@@ -1,5 +1,7 @@
 use crate::auth::session;
 use crate::config;
+use crate::events::{Event, Events};
+use crate::exif;
 use crate::payload::{DefaultResponse, Picture, PictureId};
 use crate::pictures;
 use crate::query::{self, PostgresDb};
@@ -8,75 +10,263 @@ use crate::uuid::from_serde_to_sqlx;
 use crate::uuid::SqlxUuid;
 use photon_rs::{multiple, native, PhotonImage};
 use rocket::data::{Data, ToByteUnit};
-use rocket::http::Status;
+use rocket::http::{ContentType, Status};
 use rocket::serde::json::Json;
+use rocket::serde::uuid::Uuid;
+use rocket::tokio::task;
+use rocket::State;
 use rocket_db_pools::Connection;
+use std::env;
 use std::fs;
+use std::process::Command;
 
 pub mod comment;
 pub mod comments;
 pub mod like;
+pub mod likes;
 
-fn load_user_picture(raw_bytes: Vec<u8>) -> Result<PhotonImage, String> {
+/// Check that the superposable fits in the user picture once placed.
+fn check_placement(
+    user_picture: &PhotonImage,
+    placement: &pictures::Placement,
+) -> Result<(), String> {
+    if !placement.scale.is_finite()
+        || placement.scale < pictures::MIN_SCALE
+        || placement.scale > pictures::MAX_SCALE
+    {
+        return Err(format!(
+            "scale must be between {} and {}",
+            pictures::MIN_SCALE,
+            pictures::MAX_SCALE
+        ));
+    }
+    if !placement.rotation.is_finite() {
+        return Err(String::from("invalid rotation"));
+    }
+
+    let (width, height) = placement
+        .size(*config::SUPERPOSABLES_SIDE, *config::SUPERPOSABLES_SIDE);
+    if user_picture.get_width() < width || user_picture.get_height() < height {
+        return Err(String::from("user picture too small"));
+    }
+    if placement.x > user_picture.get_width() - width
+        || placement.y.unwrap_or(0) > user_picture.get_height() - height
+    {
+        return Err(String::from("superposable out of user picture"));
+    }
+    Ok(())
+}
+
+/// Convert a HEIC or WebP picture to PNG with the configured external converter.
+/// The image library cannot decode HEIC at all and only decodes the simple
+/// lossy WebP pictures, not the lossless ones or the ones with transparency.
+async fn convert(
+    raw_bytes: Vec<u8>,
+    format: pictures::Format,
+) -> Result<Vec<u8>, ()> {
+    let (converter, extension, output_option) = match format {
+        pictures::Format::Heic => (&*config::HEIC_CONVERTER, "heic", None),
+        pictures::Format::Webp => {
+            (&*config::WEBP_CONVERTER, "webp", Some("-o"))
+        }
+        _ => return Err(()),
+    };
+    let name = Uuid::new_v4().hyphenated().to_string();
+    let input = env::temp_dir().join(format!("{}.{}", name, extension));
+    let output = env::temp_dir().join(format!("{}.png", name));
+    let converted = task::spawn_blocking(move || {
+        fs::write(&input, raw_bytes).map_err(|_| ())?;
+        let status = Command::new(converter)
+            .arg(&input)
+            .args(output_option)
+            .arg(&output)
+            .status();
+        let converted = match status {
+            Ok(status) if status.success() => fs::read(&output).map_err(|_| ()),
+            _ => Err(()),
+        };
+        _ = fs::remove_file(&input);
+        _ = fs::remove_file(&output);
+        converted
+    })
+    .await;
+    converted.unwrap_or(Err(()))
+}
+
+/// Load the user picture from its actual format, whatever the content type of
+/// the request says, and check that every superposable fits in it once placed.
+async fn load_user_picture(
+    raw_bytes: Vec<u8>,
+    layers: &[pictures::PlacedLayer],
+) -> Result<PhotonImage, String> {
+    let format = match pictures::Format::sniff(&raw_bytes) {
+        Some(format) => format,
+        None => {
+            return Err(String::from("unsupported user picture format"));
+        }
+    };
+    // the HEIC converter already applies the HEIC transformations
+    let orientation = match format {
+        pictures::Format::Heic => None,
+        _ => exif::orientation(&raw_bytes),
+    };
+    let raw_bytes = match format {
+        pictures::Format::Webp
+            if pictures::Format::is_animated_webp(&raw_bytes) =>
+        {
+            return Err(String::from(
+                "animated WebP pictures are not supported",
+            ));
+        }
+        pictures::Format::Heic | pictures::Format::Webp => {
+            match convert(raw_bytes, format).await {
+                Err(_) => {
+                    return Err(String::from("invalid user picture"));
+                }
+                Ok(raw_bytes) => raw_bytes,
+            }
+        }
+        _ => raw_bytes,
+    };
     let user_picture = match native::open_image_from_bytes(raw_bytes.as_slice())
     {
         Err(_) => {
             return Err(String::from("invalid user picture"));
         }
-        Ok(user_picture) => user_picture,
+        Ok(user_picture) => match orientation {
+            Some(orientation) => exif::orient(user_picture, orientation),
+            None => user_picture,
+        },
     };
 
-    if user_picture.get_width() < *config::SUPERPOSABLES_SIDE
-        || user_picture.get_height() < *config::SUPERPOSABLES_SIDE
-    {
-        return Err(String::from("user picture too small"));
+    for layer in layers {
+        check_placement(&user_picture, &layer.placement)?;
     }
 
     Ok(user_picture)
 }
 
+/// Put the superposables on the user picture in order and save it. The
+/// vertical position of each layer is resolved before being stored.
 async fn create_picture(
     mut user_picture: PhotonImage,
-    superposable: pictures::Superposable,
+    mut layers: Vec<pictures::PlacedLayer>,
     account_id: &SqlxUuid,
     db: &mut Connection<PostgresDb>,
 ) -> Result<Picture, ()> {
-    let filename = &format!("{}.png", superposable.as_ref());
-    let superposable_picture = match native::open_image(&format!(
-        "/{}/{}",
-        *config::SUPERPOSABLES_DIR,
-        filename
-    )) {
-        Err(_) => {
-            return Err(());
-        }
-        Ok(image) => image,
-    };
-    let y: u32 = user_picture.get_height() - *config::SUPERPOSABLES_SIDE;
-    multiple::watermark(&mut user_picture, &superposable_picture, 0, y);
-    let new_picture =
-        match query::post_picture(db, account_id, superposable).await {
+    for layer in layers.iter_mut() {
+        let superposable_picture = match native::open_image(&format!(
+            "/{}/{}",
+            *config::SUPERPOSABLES_DIR,
+            layer.file
+        )) {
             Err(_) => {
                 return Err(());
             }
-            Ok(new_picture) => new_picture,
+            Ok(image) => layer.placement.apply(image),
         };
+        let y = layer.placement.y.unwrap_or(
+            user_picture.get_height() - superposable_picture.get_height(),
+        );
+        multiple::watermark(
+            &mut user_picture,
+            &superposable_picture,
+            layer.placement.x,
+            y,
+        );
+        layer.placement.y = Some(y);
+    }
+    let jpeg = pictures::encode_jpeg(&user_picture, *config::PICTURES_QUALITY)?;
+    let new_picture = match query::post_picture(db, account_id, &layers).await {
+        Err(_) => {
+            return Err(());
+        }
+        Ok(new_picture) => new_picture,
+    };
     let filename = format!(
         "/{}/{}.jpg",
         *config::PICTURES_DIR,
         new_picture.picture_id.hyphenated()
     );
-    native::save_image(user_picture, &filename);
+    fs::write(filename, jpeg).map_err(|_| ())?;
     Ok(new_picture)
 }
 
-#[post("/<superposable>", data = "<picture>", format = "image/jpeg")]
-pub async fn post(
-    superposable: pictures::Superposable,
+/// Find the superposables of the layers in the catalog. The layers without a
+/// placement get the default one of their superposable.
+async fn place_layers(
+    layers: Vec<pictures::Layer>,
+    db: &mut Connection<PostgresDb>,
+) -> Result<Vec<pictures::PlacedLayer>, String> {
+    let names: Vec<pictures::Superposable> = layers
+        .iter()
+        .map(|layer| layer.superposable.clone())
+        .collect();
+    let catalog = query::superposables(db, Some(&names)).await;
+    layers
+        .into_iter()
+        .map(|layer| {
+            let superposable = catalog
+                .iter()
+                .find(|entry| entry.name == layer.superposable.as_ref())
+                .ok_or(format!(
+                    "unknown superposable '{}'",
+                    layer.superposable
+                ))?;
+            Ok(pictures::PlacedLayer {
+                placement: layer
+                    .placement
+                    .unwrap_or_else(|| superposable.placement()),
+                file: superposable.file.clone(),
+                superposable: layer.superposable,
+            })
+        })
+        .collect()
+}
+
+/// Create a new picture from the uploaded one with the given layers.
+async fn upload(
+    content_type: Option<&ContentType>,
     picture: Data<'_>,
+    layers: Vec<pictures::Layer>,
     sess: session::Connected,
     mut db: Connection<PostgresDb>,
+    events: &State<Events>,
 ) -> ApiResult<Picture> {
+    let accepted = content_type.is_some_and(|content_type| {
+        content_type.top() == "image"
+            && pictures::PICTURE_SUBTYPES
+                .iter()
+                .any(|subtype| content_type.sub() == *subtype)
+    });
+    if !accepted {
+        return ApiResult::Failure {
+            status: Status::UnsupportedMediaType,
+            message: format!(
+                "picture must be one of image/{}",
+                pictures::PICTURE_SUBTYPES.join(", image/")
+            ),
+        };
+    }
+    if layers.is_empty() || layers.len() > pictures::MAX_LAYERS {
+        return ApiResult::Failure {
+            status: Status::BadRequest,
+            message: format!(
+                "between 1 and {} superposables are needed",
+                pictures::MAX_LAYERS
+            ),
+        };
+    }
+    let layers = match place_layers(layers, &mut db).await {
+        Ok(layers) => layers,
+        Err(message) => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message,
+            };
+        }
+    };
+
     match picture
         .open(config::PICTURES_SIZEMAX.mebibytes())
         .into_bytes()
@@ -94,18 +284,19 @@ pub async fn post(
             ),
         },
         Ok(transfer) => {
-            let user_picture = match load_user_picture(transfer.into_inner()) {
-                Err(message) => {
-                    return ApiResult::Failure {
-                        status: Status::BadRequest,
-                        message,
-                    };
-                }
-                Ok(user_picture) => user_picture,
-            };
+            let user_picture =
+                match load_user_picture(transfer.into_inner(), &layers).await {
+                    Err(message) => {
+                        return ApiResult::Failure {
+                            status: Status::BadRequest,
+                            message,
+                        };
+                    }
+                    Ok(user_picture) => user_picture,
+                };
             match create_picture(
                 user_picture,
-                superposable,
+                layers,
                 &from_serde_to_sqlx(&sess.account_id),
                 &mut db,
             )
@@ -115,15 +306,65 @@ pub async fn post(
                     status: Status::InternalServerError,
                     message: String::from("failed to create new picture"),
                 },
-                Ok(picture) => ApiResult::Success {
-                    status: Status::Created,
-                    payload: picture,
-                },
+                Ok(picture) => {
+                    events.send(Event::Picture(picture.clone()));
+                    ApiResult::Success {
+                        status: Status::Created,
+                        payload: picture,
+                    }
+                }
             }
         }
     }
 }
 
+/// Post a new picture with the given superposable on it. The superposable is
+/// put at its default placement unless a placement is given with the 'x', 'y',
+/// 'scale' and 'rotation' parameters.
+#[allow(clippy::too_many_arguments)]
+#[post("/<superposable>?<x>&<y>&<scale>&<rotation>", data = "<picture>")]
+pub async fn post(
+    superposable: pictures::Superposable,
+    x: Option<u32>,
+    y: Option<u32>,
+    scale: Option<f64>,
+    rotation: Option<f64>,
+    content_type: Option<&ContentType>,
+    picture: Data<'_>,
+    sess: session::Connected,
+    db: Connection<PostgresDb>,
+    events: &State<Events>,
+) -> ApiResult<Picture> {
+    let placement = match (x, y, scale, rotation) {
+        (None, None, None, None) => None,
+        _ => Some(pictures::Placement {
+            x: x.unwrap_or(0),
+            y,
+            scale: scale.unwrap_or(1.0),
+            rotation: rotation.unwrap_or(0.0),
+        }),
+    };
+    let layer = pictures::Layer {
+        superposable,
+        placement,
+    };
+    upload(content_type, picture, vec![layer], sess, db, events).await
+}
+
+/// Post a new picture with several superposables stacked on it in the order
+/// of the 'layer' parameters.
+#[post("/?<layer>", data = "<picture>")]
+pub async fn post_layers(
+    layer: Vec<pictures::Layer>,
+    content_type: Option<&ContentType>,
+    picture: Data<'_>,
+    sess: session::Connected,
+    db: Connection<PostgresDb>,
+    events: &State<Events>,
+) -> ApiResult<Picture> {
+    upload(content_type, picture, layer, sess, db, events).await
+}
+
 #[delete("/", data = "<picture>", format = "json")]
 pub async fn delete(
     picture: Json<PictureId>,
@@ -1,12 +1,17 @@
 use crate::auth::session;
 use crate::config;
+use crate::events::{Event, Events};
 use crate::mail::Mailer;
-use crate::payload::Comment;
+use crate::notification::Kind;
+use crate::payload::{Comment, DefaultResponse};
 use crate::query::{self, PostgresDb};
 use crate::result::ApiResult;
 use crate::uuid::from_serde_to_sqlx;
-use rocket::http::Status;
-use rocket::serde::{json::Json, uuid::Uuid, Deserialize};
+use crate::validation;
+use lazy_static::lazy_static;
+use regex::Regex;
+use rocket::http::{Status, StatusClass};
+use rocket::serde::{json::Json, uuid::Uuid, Deserialize, Serialize};
 use rocket::State;
 use rocket_db_pools::Connection;
 
@@ -15,27 +20,88 @@ use rocket_db_pools::Connection;
 pub struct PictureComment {
     picture_id: Uuid,
     comment: String,
+    /// comment to reply to
+    parent_comment_id: Option<Uuid>,
 }
 
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CommentEdit {
+    comment_id: Uuid,
+    comment: String,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CommentId {
+    comment_id: Uuid,
+}
+
+lazy_static! {
+    static ref MENTION_REGEX: Regex =
+        Regex::new(r"(?:^|\W)@(\w+)").expect("invalid mention regex");
+}
+
+/// Usernames mentioned with '@username' in a comment.
+fn mentions(comment: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = MENTION_REGEX
+        .captures_iter(comment)
+        .map(|captures| captures[1].to_string())
+        .filter(|username| validation::username(username).is_ok())
+        .collect();
+    usernames.sort();
+    usernames.dedup();
+    usernames
+}
+
+/// Notify the picture author and the mentioned users of a new comment.
 async fn send_notification_email(
     mailer: &State<Mailer>,
     db: &mut Connection<PostgresDb>,
-    picture_id: &Uuid,
-    author: &str,
+    comment: &Comment,
 ) {
-    if let Some(email) =
-        query::has_email_notifications(db, &from_serde_to_sqlx(picture_id))
+    let url = format!(
+        "{}/index.html?picture={}",
+        config::FRONT_LINK.as_str(),
+        comment.picture_id
+    );
+
+    let picture_id = from_serde_to_sqlx(&comment.picture_id);
+    let author_id = from_serde_to_sqlx(&comment.account_id);
+
+    let author_email =
+        query::notify(db, Kind::Comment, &author_id, &picture_id, None)
             .await
-    {
-        let url = format!(
-            "{}/index.html?picture={}",
-            config::FRONT_LINK.as_str(),
-            picture_id
+            .pop();
+    if let Some(ref email) = author_email {
+        _ = mailer.send(
+            email,
+            &format!("New comment on {}", comment.picture_id),
+            &format!("{} commented on your picture: {}", comment.author, url),
         );
+    }
+
+    let usernames = mentions(&comment.content);
+    if usernames.is_empty() {
+        return;
+    }
+    let emails = query::notify(
+        db,
+        Kind::Mention,
+        &author_id,
+        &picture_id,
+        Some(&usernames),
+    )
+    .await;
+    for email in emails {
+        // the picture author has already been notified
+        if Some(&email) == author_email.as_ref() {
+            continue;
+        }
         _ = mailer.send(
             &email,
-            &format!("New comment on {}", picture_id),
-            &format!("{} commented on your picture: {}", author, url),
+            &format!("{} mentioned you", comment.author),
+            &format!("{} mentioned you in a comment: {}", comment.author, url),
         );
     }
 }
@@ -46,32 +112,153 @@ pub async fn post(
     sess: session::Connected,
     mut db: Connection<PostgresDb>,
     mailer: &State<Mailer>,
+    events: &State<Events>,
 ) -> ApiResult<Comment> {
     let picture_comment = picture_comment.into_inner();
+    let content = match validation::comment(&picture_comment.comment) {
+        Ok(content) => content,
+        Err(message) => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message,
+            };
+        }
+    };
     match query::comment(
         &mut db,
-        &picture_comment.comment,
+        &content,
         &from_serde_to_sqlx(&picture_comment.picture_id),
         &from_serde_to_sqlx(&sess.account_id),
+        picture_comment
+            .parent_comment_id
+            .as_ref()
+            .map(from_serde_to_sqlx)
+            .as_ref(),
     )
     .await
     {
         Ok(comment) => {
-            send_notification_email(
-                mailer,
-                &mut db,
-                &comment.picture_id,
-                &comment.author,
-            )
-            .await;
+            send_notification_email(mailer, &mut db, &comment).await;
+            events.send(Event::Comment(comment.clone()));
             ApiResult::Success {
                 status: Status::Created,
                 payload: comment,
             }
         }
-        Err(_) => ApiResult::Failure {
-            status: Status::BadRequest,
-            message: String::from("invalid picture id"),
+        Err(error) => {
+            // The insert returns no row for a parent comment that is not on
+            // the picture and fails on the foreign key for a missing picture
+            let status = query::error_status(&error);
+            let message = match status.class() {
+                StatusClass::ClientError => {
+                    "could not find picture or parent comment"
+                }
+                _ => "failed to post comment",
+            };
+            ApiResult::Failure {
+                status,
+                message: String::from(message),
+            }
+        }
+    }
+}
+
+/// Failure of a change on a comment that did not match any comment of the
+/// connected user. The comment either does not exist or is not theirs.
+async fn missing_comment<T: Serialize>(
+    db: &mut Connection<PostgresDb>,
+    comment_id: &Uuid,
+    action: &str,
+) -> ApiResult<T> {
+    match query::comment_exists(db, &from_serde_to_sqlx(comment_id)).await {
+        Ok(true) => ApiResult::Failure {
+            status: Status::Forbidden,
+            message: format!(
+                "current user is not allowed to {} '{}' comment",
+                action, comment_id
+            ),
+        },
+        Ok(false) => ApiResult::Failure {
+            status: Status::NotFound,
+            message: format!("could not find '{}' comment", comment_id),
+        },
+        Err(error) => ApiResult::Failure {
+            status: query::error_status(&error),
+            message: format!("failed to {} '{}' comment", action, comment_id),
+        },
+    }
+}
+
+/// Edit a comment of the connected user.
+#[put("/comment", data = "<comment_edit>", format = "json")]
+pub async fn put(
+    comment_edit: Json<CommentEdit>,
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> ApiResult<Comment> {
+    let comment_edit = comment_edit.into_inner();
+    let content = match validation::comment(&comment_edit.comment) {
+        Ok(content) => content,
+        Err(message) => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message,
+            };
+        }
+    };
+    match query::put_comment(
+        &mut db,
+        &content,
+        &from_serde_to_sqlx(&comment_edit.comment_id),
+        &from_serde_to_sqlx(&sess.account_id),
+    )
+    .await
+    {
+        Ok(Some(comment)) => ApiResult::Success {
+            status: Status::Ok,
+            payload: comment,
+        },
+        Ok(None) => {
+            missing_comment(&mut db, &comment_edit.comment_id, "edit").await
+        }
+        Err(error) => ApiResult::Failure {
+            status: query::error_status(&error),
+            message: format!(
+                "failed to edit '{}' comment",
+                comment_edit.comment_id
+            ),
+        },
+    }
+}
+
+/// Delete a comment of the connected user or on one of their pictures.
+#[delete("/comment", data = "<comment>", format = "json")]
+pub async fn delete(
+    comment: Json<CommentId>,
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> ApiResult<DefaultResponse> {
+    let comment_id = comment.into_inner().comment_id;
+    match query::delete_comment(
+        &mut db,
+        &from_serde_to_sqlx(&comment_id),
+        &from_serde_to_sqlx(&sess.account_id),
+    )
+    .await
+    {
+        Err(error) => ApiResult::Failure {
+            status: query::error_status(&error),
+            message: format!("failed to delete '{}' comment", comment_id),
+        },
+        Ok(0) => missing_comment(&mut db, &comment_id, "delete").await,
+        Ok(_) => ApiResult::Success {
+            status: Status::Ok,
+            payload: DefaultResponse {
+                response: format!(
+                    "comment '{}' successfully deleted",
+                    comment_id
+                ),
+            },
         },
     }
 }
@@ -4,12 +4,25 @@ use crate::uuid::from_serde_to_sqlx;
 use rocket::serde::{json::Json, uuid::Uuid};
 use rocket_db_pools::Connection;
 
-#[get("/comments?<picture>")]
+/// Get the comments of a picture. Every comment is returned when no count is
+/// given.
+#[get("/comments?<picture>&<index>&<count>")]
 pub async fn get(
     picture: Uuid,
+    index: Option<u32>,
+    count: Option<u32>,
     mut db: Connection<PostgresDb>,
 ) -> Option<Json<Vec<Comment>>> {
-    query::comments(&mut db, &from_serde_to_sqlx(&picture))
-        .await
-        .map(Json)
+    if count == Some(0) {
+        return None;
+    }
+
+    query::comments(
+        &mut db,
+        &from_serde_to_sqlx(&picture),
+        index.unwrap_or(0),
+        count,
+    )
+    .await
+    .map(Json)
 }
@@ -1,4 +1,6 @@
 use crate::auth::session;
+use crate::events::{Event, Events};
+use crate::notification::Kind;
 use crate::payload::DefaultResponse;
 use crate::payload::PictureId;
 use crate::query::{self, PostgresDb};
@@ -6,6 +8,7 @@ use crate::result::ApiResult;
 use crate::uuid::from_serde_to_sqlx;
 use rocket::http::Status;
 use rocket::serde::{json::Json, uuid::Uuid, Deserialize};
+use rocket::State;
 use rocket_db_pools::Connection;
 
 #[derive(Deserialize)]
@@ -20,17 +23,28 @@ pub async fn put(
     picture: Json<PictureLike>,
     sess: session::Connected,
     mut db: Connection<PostgresDb>,
+    events: &State<Events>,
 ) -> ApiResult<DefaultResponse> {
     let picture = picture.into_inner();
-    match query::put_like(
-        &mut db,
-        picture.like,
-        &from_serde_to_sqlx(&picture.picture_id),
-        &from_serde_to_sqlx(&sess.account_id),
-    )
-    .await
+    let picture_id = from_serde_to_sqlx(&picture.picture_id);
+    let account_id = from_serde_to_sqlx(&sess.account_id);
+    match query::put_like(&mut db, picture.like, &picture_id, &account_id).await
     {
-        Ok(_) => {
+        Ok(changed) => {
+            if picture.like && changed {
+                // likes are only notified in the app, not by email
+                query::notify(
+                    &mut db,
+                    Kind::Like,
+                    &account_id,
+                    &picture_id,
+                    None,
+                )
+                .await;
+            }
+            if let Some(likes) = query::like_count(&mut db, &picture_id).await {
+                events.send(Event::Likes(likes));
+            }
             let action = match picture.like {
                 true => "like",
                 false => "dislike",
@@ -56,16 +70,21 @@ pub async fn delete(
     picture: Json<PictureId>,
     sess: session::Connected,
     mut db: Connection<PostgresDb>,
+    events: &State<Events>,
 ) -> ApiResult<DefaultResponse> {
     let picture = picture.into_inner();
+    let picture_id = from_serde_to_sqlx(&picture.picture_id);
     match query::delete_like(
         &mut db,
-        &from_serde_to_sqlx(&picture.picture_id),
+        &picture_id,
         &from_serde_to_sqlx(&sess.account_id),
     )
     .await
     {
         Ok(_) => {
+            if let Some(likes) = query::like_count(&mut db, &picture_id).await {
+                events.send(Event::Likes(likes));
+            }
             let response = format!(
                 "like on picture '{}' successfully unset",
                 picture.picture_id
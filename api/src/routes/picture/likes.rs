@@ -0,0 +1,31 @@
+use crate::payload::Liker;
+use crate::query::{self, PostgresDb};
+use crate::uuid::from_serde_to_sqlx;
+use rocket::serde::{json::Json, uuid::Uuid};
+use rocket_db_pools::Connection;
+
+/// Get the users who liked or disliked a picture. Only the likes or the
+/// dislikes are returned when the 'like' parameter is given, and every one of
+/// them when no count is given.
+#[get("/likes?<picture>&<like>&<index>&<count>")]
+pub async fn get(
+    picture: Uuid,
+    like: Option<bool>,
+    index: Option<u32>,
+    count: Option<u32>,
+    mut db: Connection<PostgresDb>,
+) -> Option<Json<Vec<Liker>>> {
+    if count == Some(0) {
+        return None;
+    }
+
+    let likers = query::picture_likes(
+        &mut db,
+        &from_serde_to_sqlx(&picture),
+        like,
+        index.unwrap_or(0),
+        count,
+    )
+    .await;
+    Some(Json(likers))
+}
@@ -1,31 +1,55 @@
 use crate::auth::session;
-use crate::payload::Picture;
+use crate::payload::{DefaultResponse, Picture};
 use crate::pictures;
 use crate::query::{self, PostgresDb};
+use crate::result::ApiResult;
 use crate::uuid::from_serde_to_sqlx;
+use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::serde::uuid::Uuid;
+use rocket::time::OffsetDateTime;
 use rocket_db_pools::Connection;
 
 pub mod superposable;
 
-#[get("/?<index>&<count>&<username>&<superposable>&<start>&<end>&<picture>")]
+/// Get the gallery pictures, most recent first by default. The next pictures
+/// can be requested with the cursor of the last one given as the 'after'
+/// parameter. The 'index' page number is ignored in that case. The 'window'
+/// parameter sets the 'start' filter to the last day or week when it is not
+/// given. The 'following' filter requires a session.
+#[get(
+    "/?<index>&<count>&<username>&<superposable>&<start>&<end>&<picture>&<following>&<sort>&<window>&<after>"
+)]
 pub async fn get(
-    index: u32,
+    index: Option<u32>,
     count: u32,
     username: Option<&str>,
     mut superposable: Vec<pictures::Superposable>,
     start: Option<i64>,
     end: Option<i64>,
     picture: Option<Uuid>,
+    following: Option<bool>,
+    sort: Option<pictures::Sort>,
+    window: Option<pictures::Window>,
+    after: Option<pictures::Cursor>,
     mut db: Connection<PostgresDb>,
     is_connected: session::IsConnected,
-) -> Option<Json<Vec<Picture>>> {
+) -> Result<Option<Json<Vec<Picture>>>, ApiResult<DefaultResponse>> {
     superposable.sort();
     superposable.dedup();
 
     if count == 0 {
-        return None;
+        return Ok(None);
+    }
+
+    let following = following.unwrap_or(false);
+    if following && is_connected.0.is_none() {
+        return Err(ApiResult::Failure {
+            status: Status::Unauthorized,
+            message: String::from(
+                "user must be logged in to see the followed users' pictures",
+            ),
+        });
     }
 
     let account_id = match is_connected.0 {
@@ -38,7 +62,19 @@ pub async fn get(
         None => None,
     };
 
-    query::pictures(
+    let start = match window.and_then(|window| window.duration()) {
+        Some(duration) if start.is_none() => {
+            Some(OffsetDateTime::now_utc().unix_timestamp() - duration)
+        }
+        _ => start,
+    };
+
+    let index = match after {
+        Some(_) => 0,
+        None => index.unwrap_or(0),
+    };
+
+    Ok(query::pictures(
         &mut db,
         index,
         count,
@@ -48,7 +84,10 @@ pub async fn get(
         start,
         end,
         picture_id,
+        following,
+        sort.unwrap_or(pictures::Sort::Newest),
+        after.as_ref(),
     )
     .await
-    .map(Json)
+    .map(Json))
 }
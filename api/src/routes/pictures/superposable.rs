@@ -1,17 +1,195 @@
+use crate::auth::session;
+use crate::config;
+use crate::payload::{CatalogSuperposable, DefaultResponse};
 use crate::pictures;
+use crate::query::{self, PostgresDb};
+use crate::result::ApiResult;
+use crate::validation;
+use photon_rs::native;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
 use rocket::serde::json::Json;
-use strum::IntoEnumIterator;
+use rocket_db_pools::Connection;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
 
-#[get("/superposable")]
-pub async fn get() -> Option<Json<Vec<String>>> {
-    let mut superposables = Vec::new();
-    for superposable in pictures::Superposable::iter() {
-        superposables.push(superposable.as_ref().to_string());
+// Signature at the start of every PNG file.
+const PNG_SIGNATURE: [u8; 8] =
+    [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Offset of the color type in the IHDR chunk which is always the first one.
+const PNG_COLOR_TYPE_OFFSET: usize = 25;
+
+// Color types of the PNG format with an alpha channel: grayscale with alpha
+// and RGBA.
+const PNG_ALPHA_COLOR_TYPES: [u8; 2] = [4, 6];
+
+/// Check that the superposable is a PNG file with an alpha channel actually
+/// used and with the size of the superposables.
+fn check_superposable(raw_bytes: &[u8]) -> Result<(), String> {
+    if raw_bytes.len() <= PNG_COLOR_TYPE_OFFSET
+        || raw_bytes[..PNG_SIGNATURE.len()] != PNG_SIGNATURE
+    {
+        return Err(String::from("superposable must be a PNG file"));
+    }
+    if !PNG_ALPHA_COLOR_TYPES.contains(&raw_bytes[PNG_COLOR_TYPE_OFFSET]) {
+        return Err(String::from("superposable must have an alpha channel"));
     }
 
+    let superposable = match native::open_image_from_bytes(raw_bytes) {
+        Err(_) => return Err(String::from("invalid superposable picture")),
+        Ok(superposable) => superposable,
+    };
+    let side = *config::SUPERPOSABLES_SIDE;
+    if superposable.get_width() != side || superposable.get_height() != side {
+        return Err(format!("superposable must be {}x{} pixels", side, side));
+    }
+    // every fourth byte is the alpha value of a pixel
+    if !superposable
+        .get_raw_pixels()
+        .iter()
+        .skip(3)
+        .step_by(4)
+        .any(|alpha| *alpha < u8::MAX)
+    {
+        return Err(String::from("superposable must have transparent pixels"));
+    }
+    Ok(())
+}
+
+/// Get the catalog of the enabled superposables with their default placement.
+#[get("/superposable")]
+pub async fn get(
+    mut db: Connection<PostgresDb>,
+) -> Option<Json<Vec<CatalogSuperposable>>> {
+    let superposables: Vec<CatalogSuperposable> =
+        query::superposables(&mut db, None)
+            .await
+            .iter()
+            .map(CatalogSuperposable::from)
+            .collect();
+
     if superposables.is_empty() {
         return None;
     }
 
     Some(Json(superposables))
 }
+
+/// Add a superposable to the catalog. The default placement is the bottom left
+/// corner at native size unless given with the 'x', 'y', 'scale' and
+/// 'rotation' parameters.
+#[allow(clippy::too_many_arguments)]
+#[post(
+    "/superposable?<name>&<category>&<x>&<y>&<scale>&<rotation>",
+    data = "<superposable>",
+    format = "image/png"
+)]
+pub async fn post(
+    name: pictures::Superposable,
+    category: Option<&str>,
+    x: Option<u32>,
+    y: Option<u32>,
+    scale: Option<f64>,
+    rotation: Option<f64>,
+    superposable: Data<'_>,
+    admin: session::Admin,
+    mut db: Connection<PostgresDb>,
+) -> ApiResult<DefaultResponse> {
+    let category = category.unwrap_or("pepe");
+    if let Err(message) = validation::superposable(category) {
+        return ApiResult::Failure {
+            status: Status::BadRequest,
+            message,
+        };
+    }
+    let placement = pictures::Placement {
+        x: x.unwrap_or(0),
+        y,
+        scale: scale.unwrap_or(1.0),
+        rotation: rotation.unwrap_or(0.0),
+    };
+    if !placement.scale.is_finite()
+        || placement.scale < pictures::MIN_SCALE
+        || placement.scale > pictures::MAX_SCALE
+        || !placement.rotation.is_finite()
+    {
+        return ApiResult::Failure {
+            status: Status::BadRequest,
+            message: String::from("invalid default placement"),
+        };
+    }
+
+    let raw_bytes = match superposable
+        .open(config::PICTURES_SIZEMAX.mebibytes())
+        .into_bytes()
+        .await
+    {
+        Err(_) => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: String::from("file upload failure"),
+            };
+        }
+        Ok(transfer) if !transfer.is_complete() => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: format!(
+                    "file too big ({} MiB max)",
+                    *config::PICTURES_SIZEMAX
+                ),
+            };
+        }
+        Ok(transfer) => transfer.into_inner(),
+    };
+    if let Err(message) = check_superposable(&raw_bytes) {
+        return ApiResult::Failure {
+            status: Status::BadRequest,
+            message,
+        };
+    }
+
+    // The row is created first so that an existing superposable is never
+    // replaced and the file is only created if it does not exist yet
+    let file = format!("{}.png", name);
+    if let Err(error) =
+        query::post_superposable(&mut db, &name, &file, category, &placement)
+            .await
+    {
+        return ApiResult::Failure {
+            status: query::error_status(&error),
+            message: format!("failed to add superposable '{}'", name),
+        };
+    }
+    let path = format!("/{}/{}", *config::SUPERPOSABLES_DIR, file);
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&raw_bytes));
+    if let Err(error) = written {
+        _ = query::delete_superposable(&mut db, &name).await;
+        return match error.kind() {
+            ErrorKind::AlreadyExists => ApiResult::Failure {
+                status: Status::Conflict,
+                message: format!("file '{}' already exists", file),
+            },
+            _ => {
+                // do not leave a partially written file behind
+                _ = fs::remove_file(&path);
+                ApiResult::Failure {
+                    status: Status::InternalServerError,
+                    message: String::from("failed to save superposable"),
+                }
+            }
+        };
+    }
+
+    info!("superposable '{}' added by '{}'", name, admin.0.username);
+    ApiResult::Success {
+        status: Status::Created,
+        payload: DefaultResponse {
+            response: format!("superposable '{}' successfully added", name),
+        },
+    }
+}
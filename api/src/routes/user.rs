@@ -1,23 +1,35 @@
 pub mod confirm;
 pub mod email;
+pub mod export;
+pub mod follow;
+pub mod likes;
 pub mod login;
 pub mod logout;
+pub mod notifications;
 pub mod register;
 pub mod reset;
+pub mod sessions;
+pub mod two_factor;
 
-use crate::auth::session;
+use crate::auth::{password, session};
 use crate::cache::Cache;
 use crate::config;
 use crate::mail::Mailer;
 use crate::payload::{DefaultResponse, Email, Token, UserProfile};
-use crate::query::{get_user_by_account_id, put_user, PostgresDb};
+use crate::query::{
+    delete_account, error_status, get_user_by_account_id,
+    put_notification_preferences, put_user, PostgresDb,
+};
+use crate::rate_limit::Limiter;
 use crate::result::ApiResult;
-use crate::uuid::from_serde_to_sqlx;
+use crate::uuid::{from_serde_to_sqlx, from_sqlx_to_serde};
 use crate::validation;
-use rocket::http::Status;
+use rocket::http::{CookieJar, Status};
 use rocket::serde::{json::Json, Deserialize, Serialize};
 use rocket::State;
+use rocket_db_pools::sqlx::Acquire;
 use rocket_db_pools::Connection;
+use std::fs;
 use std::time::Duration;
 
 #[derive(Serialize, Deserialize)]
@@ -27,6 +39,16 @@ pub struct UserChanges {
     password: Option<String>,
     email: Option<String>,
     email_notifications: Option<bool>,
+    comment_notifications: Option<bool>,
+    like_notifications: Option<bool>,
+    mention_notifications: Option<bool>,
+}
+
+/// Request payload for the account deletion
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AccountDeletion {
+    password: String,
 }
 
 // Time during which the email can be used in seconds.
@@ -47,6 +69,9 @@ pub async fn put(
         password: None,
         email: None,
         email_notifications: None,
+        comment_notifications: None,
+        like_notifications: None,
+        mention_notifications: None,
     } = user_changes
     {
         return ApiResult::Failure {
@@ -86,11 +111,16 @@ pub async fn put(
         let new_email = Email {
             email: email.clone(),
         };
-        new_emails.set(
-            &token_name,
-            &new_email,
-            Duration::from_secs(EMAIL_TOKEN_LIFETIME),
-        );
+        if let Err(error) = new_emails
+            .set(
+                &token_name,
+                &new_email,
+                Duration::from_secs(EMAIL_TOKEN_LIFETIME),
+            )
+            .await
+        {
+            return error.into();
+        }
         let link = format!(
             "{}/email.html?token={}",
             config::FRONT_LINK.as_str(),
@@ -99,9 +129,20 @@ pub async fn put(
         _ = mailer.send(email, "Confirm your email", &link);
     }
 
-    match put_user(
-        &mut db,
-        &from_serde_to_sqlx(&sess.account_id),
+    let account_id = from_serde_to_sqlx(&sess.account_id);
+    // the account and notification changes are saved together or not at all
+    let mut tx = match (&mut **db).begin().await {
+        Ok(tx) => tx,
+        Err(error) => {
+            return ApiResult::Failure {
+                status: error_status(&error),
+                message: String::from("Failed to update user account."),
+            }
+        }
+    };
+    let result = match put_user(
+        &mut tx,
+        &account_id,
         user_changes.username,
         user_changes.password,
         None,
@@ -109,6 +150,24 @@ pub async fn put(
     )
     .await
     {
+        Ok(_) => {
+            put_notification_preferences(
+                &mut tx,
+                &account_id,
+                user_changes.comment_notifications,
+                user_changes.like_notifications,
+                user_changes.mention_notifications,
+            )
+            .await
+        }
+        Err(_) => Err(()),
+    };
+    let result = match result {
+        Ok(_) => tx.commit().await.map_err(|_| ()),
+        Err(_) => Err(()),
+    };
+
+    match result {
         Ok(_) => ApiResult::Success {
             status: Status::Ok,
             payload: DefaultResponse {
@@ -134,7 +193,81 @@ pub async fn get(
             username: user.username,
             email: user.email,
             email_notifications: user.email_notifications,
+            comment_notifications: user.comment_notifications,
+            like_notifications: user.like_notifications,
+            mention_notifications: user.mention_notifications,
+            two_factor: user.totp_secret.is_some(),
         })),
         None => None,
     }
 }
+
+/// Delete the account of the connected user with all of their pictures,
+/// likes and comments. The password must be given again.
+#[delete("/", data = "<deletion>", format = "json")]
+pub async fn delete(
+    deletion: Json<AccountDeletion>,
+    sess: session::Connected,
+    limiter: Limiter<'_>,
+    mut db: Connection<PostgresDb>,
+    sessions: &State<Cache<session::Active>>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<DefaultResponse> {
+    let account_id = from_serde_to_sqlx(&sess.account_id);
+    let identifier = sess.account_id.to_string();
+    if let Err(refusal) = limiter.check(&identifier).await {
+        return refusal.into();
+    }
+
+    let password_hash = match get_user_by_account_id(&account_id, &mut db).await
+    {
+        Some(user) => user.password_hash,
+        None => {
+            return ApiResult::Failure {
+                status: Status::InternalServerError,
+                message: String::from("could not find user account"),
+            };
+        }
+    };
+    if !password::verify(&deletion.password, &password_hash).is_valid() {
+        limiter.hit(Some(&identifier)).await;
+        return ApiResult::Failure {
+            status: Status::BadRequest,
+            message: String::from("invalid password"),
+        };
+    }
+
+    let picture_ids = match delete_account(&mut db, &account_id).await {
+        Ok(picture_ids) => picture_ids,
+        Err(_) => {
+            return ApiResult::Failure {
+                status: Status::InternalServerError,
+                message: String::from("failed to delete user account"),
+            };
+        }
+    };
+
+    // The account is gone at this point so a file that cannot be removed
+    // should not fail the request.
+    for picture_id in picture_ids {
+        let filename = format!(
+            "/{}/{}.jpg",
+            *config::PICTURES_DIR,
+            from_sqlx_to_serde(&picture_id).hyphenated()
+        );
+        _ = fs::remove_file(&filename);
+    }
+
+    // the sessions of a deleted account are rejected anyway
+    if let Err(error) = sessions::revoke_all(&sess, sessions).await {
+        error!("failed to revoke sessions: {}", error);
+    }
+    session::remove_cookies(cookies);
+
+    ApiResult::Success {
+        status: Status::Ok,
+        payload: DefaultResponse {
+            response: format!("user account '{}' deleted", sess.username),
+        },
+    }
+}
@@ -1,4 +1,4 @@
-use super::login::{login, Credentials};
+use super::login::{find_account, login, Credentials, Pending};
 use crate::{
     auth::session,
     cache::Cache,
@@ -14,21 +14,25 @@ use rocket::{
 use rocket_db_pools::Connection;
 
 /// Confirm new user account with the registration token.
+#[allow(clippy::too_many_arguments)]
 #[post("/confirm", data = "<registration_token>", format = "json")]
 pub async fn post(
     registration_token: Json<Token>,
     _sess: session::Unconnected,
+    device: session::Device,
     mut db: Connection<PostgresDb>,
     new_users: &State<Cache<NewUser>>,
-    sessions: &State<Cache<session::Connected>>,
+    sessions: &State<Cache<session::Active>>,
+    pending_logins: &State<Cache<Pending>>,
     cookies: &CookieJar<'_>,
 ) -> ApiResult<DefaultResponse> {
     let token = registration_token.into_inner();
     let token_name = format!("registration_token:{}", token);
 
-    let new_user = match new_users.del(&token_name) {
-        Some(item) => item,
-        None => {
+    let new_user = match new_users.del(&token_name).await {
+        Err(error) => return error.into(),
+        Ok(Some(item)) => item,
+        Ok(None) => {
             return ApiResult::Failure {
                 status: Status::BadRequest,
                 message: format!("invalid registration token '{}'", token),
@@ -38,15 +42,25 @@ pub async fn post(
     match query::create_account(&mut db, &new_user).await {
         Ok(_) => {
             let credentials = Credentials {
-                username: new_user.username,
+                identifier: new_user.username,
                 password: new_user.password,
+                remember_me: false,
             };
-            let response = match login(&credentials, &mut db, cookies, sessions)
-                .await
+            let account = find_account(&credentials.identifier, &mut db).await;
+            let response = match login(
+                &credentials,
+                account,
+                &device,
+                &mut db,
+                cookies,
+                sessions,
+                pending_logins,
+            )
+            .await
             {
                 Ok(_) => format!(
                     "Great success! New user account '{}' has been created!",
-                    &credentials.username
+                    &credentials.identifier
                 ),
                 Err(_) => "account created, but could not log in".to_string(),
             };
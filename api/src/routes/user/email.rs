@@ -1,3 +1,4 @@
+use crate::uuid::from_serde_to_sqlx;
 use crate::{
     auth::session,
     cache::Cache,
@@ -5,7 +6,6 @@ use crate::{
     query::{self, PostgresDb},
     result::ApiResult,
 };
-use crate::uuid::from_serde_to_sqlx;
 use rocket::http::Status;
 use rocket::serde::json::Json;
 use rocket::State;
@@ -22,9 +22,10 @@ pub async fn post(
     let token = email_token.into_inner();
     let token_name = format!("email_token:{}", token);
 
-    let email = match new_emails.del(&token_name) {
-        Some(item) => item.email,
-        None => {
+    let email = match new_emails.del(&token_name).await {
+        Err(error) => return error.into(),
+        Ok(Some(item)) => item.email,
+        Ok(None) => {
             return ApiResult::Failure {
                 status: Status::BadRequest,
                 message: format!("invalid email token '{}'", token),
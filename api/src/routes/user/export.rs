@@ -0,0 +1,165 @@
+use crate::auth::session;
+use crate::config;
+use crate::payload::{Account, DefaultResponse};
+use crate::pictures;
+use crate::query::{self, PostgresDb};
+use crate::result::ApiResult;
+use crate::uuid::{from_serde_to_sqlx, from_sqlx_to_serde};
+use rocket::http::{ContentType, Header, Status};
+use rocket::response::{self, Responder};
+use rocket::serde::{json, uuid::Uuid};
+use rocket::tokio::io::{self, DuplexStream};
+use rocket::{Request, Response};
+use rocket_db_pools::Connection;
+use std::path::Path;
+use tokio_tar::{Builder, Header as TarHeader};
+
+// Size of the buffer between the archive builder and the response body.
+const ARCHIVE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Tar archive streamed to the client while it is being built.
+pub struct Archive {
+    filename: String,
+    reader: DuplexStream,
+}
+
+impl<'r> Responder<'r, 'static> for Archive {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::new("application", "x-tar"))
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.filename),
+            ))
+            .streamed_body(self.reader)
+            .ok()
+    }
+}
+
+/// Add a file with the given content to the archive.
+async fn append_json(
+    builder: &mut Builder<DuplexStream>,
+    path: &str,
+    content: String,
+) -> io::Result<()> {
+    let mut header = TarHeader::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        rocket::time::OffsetDateTime::now_utc().unix_timestamp() as u64
+    );
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, content.as_bytes())
+        .await
+}
+
+/// Write the json files and the picture files to the archive. The picture
+/// files are read from the disk as the archive is consumed by the client.
+async fn write_archive(
+    mut builder: Builder<DuplexStream>,
+    json_files: Vec<(&str, String)>,
+    picture_ids: Vec<Uuid>,
+) -> io::Result<()> {
+    for (path, content) in json_files {
+        append_json(&mut builder, path, content).await?;
+    }
+    for picture_id in picture_ids {
+        let filename = format!("{}.jpg", picture_id.hyphenated());
+        let path = format!("/{}/{}", *config::PICTURES_DIR, filename);
+        // a missing file should not prevent the export of the others
+        if Path::new(&path).exists() {
+            builder
+                .append_path_with_name(&path, format!("pictures/{}", filename))
+                .await?;
+        }
+    }
+    builder.finish().await
+}
+
+/// Export the personal data of the connected user as a tar archive. It
+/// contains the account data, the pictures with their metadata, the comments
+/// and the likes of the user.
+#[get("/export")]
+pub async fn get(
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> Result<Archive, ApiResult<DefaultResponse>> {
+    let account_id = from_serde_to_sqlx(&sess.account_id);
+    let account =
+        match query::get_user_by_account_id(&account_id, &mut db).await {
+            Some(account) => account,
+            None => {
+                return Err(ApiResult::Failure {
+                    status: Status::InternalServerError,
+                    message: String::from("could not find user account"),
+                });
+            }
+        };
+
+    let pictures = query::pictures(
+        &mut db,
+        0,
+        u32::MAX,
+        Some(account_id),
+        Some(&account.username),
+        vec![],
+        None,
+        None,
+        None,
+        false,
+        pictures::Sort::Newest,
+        None,
+    )
+    .await
+    .unwrap_or_default();
+    let comments = query::user_comments(&mut db, &account_id).await;
+    let likes = query::user_likes(&mut db, &account_id, None, 0, None).await;
+
+    let picture_ids =
+        pictures.iter().map(|picture| picture.picture_id).collect();
+    let username = account.username.clone();
+    let account = Account {
+        account_id: from_sqlx_to_serde(&account.account_id),
+        email: account.email,
+        username: account.username,
+        email_notifications: account.email_notifications,
+        comment_notifications: account.comment_notifications,
+        like_notifications: account.like_notifications,
+        mention_notifications: account.mention_notifications,
+        two_factor: account.totp_secret.is_some(),
+    };
+    let json_files = match (
+        json::to_pretty_string(&account),
+        json::to_pretty_string(&pictures),
+        json::to_pretty_string(&comments),
+        json::to_pretty_string(&likes),
+    ) {
+        (Ok(account), Ok(pictures), Ok(comments), Ok(likes)) => vec![
+            ("account.json", account),
+            ("pictures.json", pictures),
+            ("comments.json", comments),
+            ("likes.json", likes),
+        ],
+        _ => {
+            return Err(ApiResult::Failure {
+                status: Status::InternalServerError,
+                message: String::from("failed to serialize user data"),
+            });
+        }
+    };
+
+    let (writer, reader) = io::duplex(ARCHIVE_BUFFER_SIZE);
+    let builder = Builder::new(writer);
+    rocket::tokio::spawn(async move {
+        if let Err(error) =
+            write_archive(builder, json_files, picture_ids).await
+        {
+            error!("failed to export user data: {}", error);
+        }
+    });
+    Ok(Archive {
+        filename: format!("pepecam-{}.tar", username),
+        reader,
+    })
+}
@@ -0,0 +1,101 @@
+use crate::auth::session;
+use crate::payload::DefaultResponse;
+use crate::query::{self, PostgresDb};
+use crate::result::ApiResult;
+use crate::uuid::from_serde_to_sqlx;
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize};
+use rocket_db_pools::Connection;
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Follow {
+    username: String,
+}
+
+#[put("/follow", data = "<follow>", format = "json")]
+pub async fn put(
+    follow: Json<Follow>,
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> ApiResult<DefaultResponse> {
+    let username = follow.into_inner().username;
+    let followed = match query::get_user_by_username(&username, &mut db).await {
+        Some(account) => account,
+        None => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: format!("could not find user '{}'", username),
+            };
+        }
+    };
+    if followed.account_id == from_serde_to_sqlx(&sess.account_id) {
+        return ApiResult::Failure {
+            status: Status::BadRequest,
+            message: String::from("users cannot follow themselves"),
+        };
+    }
+
+    match query::put_follow(
+        &mut db,
+        &from_serde_to_sqlx(&sess.account_id),
+        &followed.account_id,
+    )
+    .await
+    {
+        Ok(_) => ApiResult::Success {
+            status: Status::Created,
+            payload: DefaultResponse {
+                response: format!("user '{}' successfully followed", username),
+            },
+        },
+        Err(_) => ApiResult::Failure {
+            status: Status::InternalServerError,
+            message: format!("failed to follow user '{}'", username),
+        },
+    }
+}
+
+#[delete("/follow", data = "<follow>", format = "json")]
+pub async fn delete(
+    follow: Json<Follow>,
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> ApiResult<DefaultResponse> {
+    let username = follow.into_inner().username;
+    let followed = match query::get_user_by_username(&username, &mut db).await {
+        Some(account) => account,
+        None => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: format!("could not find user '{}'", username),
+            };
+        }
+    };
+
+    match query::delete_follow(
+        &mut db,
+        &from_serde_to_sqlx(&sess.account_id),
+        &followed.account_id,
+    )
+    .await
+    {
+        Err(_) => ApiResult::Failure {
+            status: Status::InternalServerError,
+            message: format!("failed to unfollow user '{}'", username),
+        },
+        Ok(0) => ApiResult::Failure {
+            status: Status::BadRequest,
+            message: format!("user '{}' is not followed", username),
+        },
+        Ok(_) => ApiResult::Success {
+            status: Status::Ok,
+            payload: DefaultResponse {
+                response: format!(
+                    "user '{}' successfully unfollowed",
+                    username
+                ),
+            },
+        },
+    }
+}
@@ -0,0 +1,32 @@
+use crate::auth::session;
+use crate::payload::Like;
+use crate::query::{self, PostgresDb};
+use crate::uuid::from_serde_to_sqlx;
+use rocket::serde::json::Json;
+use rocket_db_pools::Connection;
+
+/// Get the pictures liked or disliked by the connected user, most recent
+/// first. Only the likes or the dislikes are returned when the 'like'
+/// parameter is given, and every one of them when no count is given.
+#[get("/likes?<like>&<index>&<count>")]
+pub async fn get(
+    like: Option<bool>,
+    index: Option<u32>,
+    count: Option<u32>,
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> Option<Json<Vec<Like>>> {
+    if count == Some(0) {
+        return None;
+    }
+
+    let likes = query::user_likes(
+        &mut db,
+        &from_serde_to_sqlx(&sess.account_id),
+        like,
+        index.unwrap_or(0),
+        count,
+    )
+    .await;
+    Some(Json(likes))
+}
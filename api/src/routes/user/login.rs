@@ -1,77 +1,306 @@
-use crate::auth;
+use super::two_factor;
+use crate::auth::password::{self, Verification};
 use crate::auth::session;
-use crate::cache::Cache;
-use crate::payload::DefaultResponse;
-use crate::query::{self, PostgresDb};
+use crate::cache::{self, Cache};
+use crate::config;
+use crate::payload::{DefaultResponse, Login, Token};
+use crate::query::{self, types, PostgresDb};
+use crate::rate_limit::Limiter;
 use crate::result::ApiResult;
-use crate::uuid::from_sqlx_to_serde;
-use rocket::http::{Cookie, CookieJar, Status};
-use rocket::serde::{json::Json, Deserialize};
-use rocket::time::{Duration, OffsetDateTime};
+use crate::uuid::{from_serde_to_sqlx, from_sqlx_to_serde};
+use crate::validation;
+use rocket::http::{CookieJar, Status};
+use rocket::serde::{json::Json, uuid::Uuid, Deserialize, Serialize};
 use rocket::State;
 use rocket_db_pools::Connection;
+use std::time::Duration;
 
 #[derive(Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct Credentials {
-    pub username: String,
+    /// username or email, 'username' is kept for backwards compatibility
+    #[serde(alias = "username")]
+    pub identifier: String,
     pub password: String,
+    #[serde(default)]
+    pub remember_me: bool,
 }
 
-// Session default duration (1 day in seconds)
-const SESSION_DURATION: f64 = 86_400.0;
+/// Request payload for the second step of the login
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SecondFactor {
+    two_factor_token: Uuid,
+    code: String,
+}
+
+/// Login waiting for the second factor stored in the cache
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Pending {
+    account_id: Uuid,
+    username: String,
+    remember_me: bool,
+}
+
+/// Outcome of a successful password check.
+pub enum Outcome {
+    /// The session has been created.
+    Connected,
+    /// The user must give a second factor along with this token.
+    TwoFactor(Token),
+}
+
+/// Reason of a failed login.
+pub enum Failure {
+    /// The credentials do not match any account.
+    InvalidCredentials,
+    /// The session or the pending login could not be stored.
+    Storage(cache::Error),
+}
+
+impl From<cache::Error> for Failure {
+    fn from(error: cache::Error) -> Self {
+        Failure::Storage(error)
+    }
+}
+
+// Time during which the second factor can be given in seconds.
+const PENDING_LOGIN_LIFETIME: u64 = 300; // 5 minutes
+
+/// Create the session of the given user.
+async fn open_session(
+    account_id: Uuid,
+    username: &str,
+    remember_me: bool,
+    device: &session::Device,
+    cookies: &CookieJar<'_>,
+    sessions: &State<Cache<session::Active>>,
+) -> Result<(), cache::Error> {
+    let session = session::Connected::new(account_id, username);
+    let duration = match remember_me {
+        true => *config::REMEMBER_ME_DURATION,
+        false => *config::SESSION_DURATION,
+    };
+    let active =
+        session::Active::new(&session, device, Duration::from_secs(duration));
+    active.save(sessions).await?;
+    session::add_cookies(cookies, &active);
+    Ok(())
+}
+
+/// Find the account matching the identifier, either an email or a username.
+pub async fn find_account(
+    identifier: &str,
+    db: &mut Connection<PostgresDb>,
+) -> Option<types::Account> {
+    match validation::email(identifier) {
+        Ok(_) => query::get_user_by_email(identifier, db).await,
+        Err(_) => query::get_user_by_username(identifier, db).await,
+    }
+}
 
-/// Helper function checking credentials and creating the session on success.
+/// Helper function checking credentials against the account found from their
+/// identifier and creating the session on success.
+/// Outdated password hashes are replaced on the way.
+/// If the user has enabled two-factor authentication a pending login is
+/// created instead.
 pub async fn login(
     credentials: &Credentials,
+    account: Option<types::Account>,
+    device: &session::Device,
     db: &mut Connection<PostgresDb>,
     cookies: &CookieJar<'_>,
-    sessions: &State<Cache<session::Connected>>,
-) -> Result<String, String> {
-    if let Some(account) =
-        query::get_user_by_username(&credentials.username, db).await
-    {
-        if auth::password::verify(&credentials.password, &account.password_hash)
-        {
-            let session = session::Connected::new(
-                from_sqlx_to_serde(&account.account_id),
+    sessions: &State<Cache<session::Active>>,
+    pending_logins: &State<Cache<Pending>>,
+) -> Result<Outcome, Failure> {
+    if let Some(account) = account {
+        let verification =
+            password::verify(&credentials.password, &account.password_hash);
+        if verification.is_valid() {
+            // Migrate the hash to the current Argon2 configuration. This is
+            // not fatal since the old hash stays valid.
+            if verification == Verification::Outdated {
+                _ = query::put_user(
+                    db,
+                    &account.account_id,
+                    None,
+                    Some(credentials.password.clone()),
+                    None,
+                    None,
+                )
+                .await;
+            }
+            let account_id = from_sqlx_to_serde(&account.account_id);
+            if account.totp_secret.is_some() {
+                let token = Token::new();
+                let pending = Pending {
+                    account_id,
+                    username: account.username,
+                    remember_me: credentials.remember_me,
+                };
+                pending_logins
+                    .set(
+                        &format!("login_token:{}", token),
+                        &pending,
+                        Duration::from_secs(PENDING_LOGIN_LIFETIME),
+                    )
+                    .await?;
+                return Ok(Outcome::TwoFactor(token));
+            }
+            open_session(
+                account_id,
                 &account.username,
-            );
-            sessions.set(
-                &session.account_id.to_string(),
-                &session,
-                std::time::Duration::from_secs_f64(SESSION_DURATION),
-            );
-            let mut cookie = Cookie::new("session", session.to_string());
-            cookie.set_expires(
-                OffsetDateTime::now_utc()
-                    + Duration::seconds_f64(SESSION_DURATION),
-            );
-            cookies.add(cookie);
-            return Ok(String::from("great authentication success!"));
+                credentials.remember_me,
+                device,
+                cookies,
+                sessions,
+            )
+            .await?;
+            return Ok(Outcome::Connected);
         }
     }
-    Err(String::from("invalid credentials"))
+    Err(Failure::InvalidCredentials)
 }
 
 /// Route handler to login into the application.
+#[allow(clippy::too_many_arguments)]
 #[post("/login", data = "<credentials>", format = "json")]
 pub async fn post(
     credentials: Json<Credentials>,
     _sess: session::Unconnected,
+    limiter: Limiter<'_>,
+    device: session::Device,
     mut db: Connection<PostgresDb>,
-    sessions: &State<Cache<session::Connected>>,
+    sessions: &State<Cache<session::Active>>,
+    pending_logins: &State<Cache<Pending>>,
     cookies: &CookieJar<'_>,
-) -> ApiResult<DefaultResponse> {
+) -> ApiResult<Login> {
     let credentials = credentials.into_inner();
-    match login(&credentials, &mut db, cookies, sessions).await {
-        Ok(response) => ApiResult::Success {
-            status: Status::Ok,
-            payload: DefaultResponse { response },
-        },
-        Err(message) => ApiResult::Failure {
+    let account = find_account(&credentials.identifier, &mut db).await;
+    // The attempts are counted per account whether the username or the email
+    // is given. Only the identifiers matching no account are counted as is.
+    let limited = match account {
+        Some(ref account) => {
+            format!("account:{}", from_sqlx_to_serde(&account.account_id))
+        }
+        None => format!("identifier:{}", credentials.identifier),
+    };
+    if let Err(refusal) = limiter.check(&limited).await {
+        return refusal.into();
+    }
+
+    match login(
+        &credentials,
+        account,
+        &device,
+        &mut db,
+        cookies,
+        sessions,
+        pending_logins,
+    )
+    .await
+    {
+        Ok(Outcome::Connected) => {
+            limiter.reset(&limited).await;
+            ApiResult::Success {
+                status: Status::Ok,
+                payload: Login {
+                    response: String::from("great authentication success!"),
+                    two_factor_token: None,
+                },
+            }
+        }
+        Ok(Outcome::TwoFactor(token)) => {
+            limiter.reset(&limited).await;
+            ApiResult::Success {
+                status: Status::Accepted,
+                payload: Login {
+                    response: String::from("two-factor code required"),
+                    two_factor_token: Some(token.token),
+                },
+            }
+        }
+        Err(Failure::InvalidCredentials) => {
+            limiter.hit(Some(&limited)).await;
+            ApiResult::Failure {
+                status: Status::BadRequest,
+                message: String::from("invalid credentials"),
+            }
+        }
+        Err(Failure::Storage(error)) => error.into(),
+    }
+}
+
+/// Route handler completing the login with a two-factor or recovery code.
+#[allow(clippy::too_many_arguments)]
+#[post("/login/2fa", data = "<second_factor>", format = "json")]
+pub async fn post_two_factor(
+    second_factor: Json<SecondFactor>,
+    _sess: session::Unconnected,
+    limiter: Limiter<'_>,
+    device: session::Device,
+    mut db: Connection<PostgresDb>,
+    sessions: &State<Cache<session::Active>>,
+    pending_logins: &State<Cache<Pending>>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<DefaultResponse> {
+    let second_factor = second_factor.into_inner();
+    let token_name = format!("login_token:{}", second_factor.two_factor_token);
+    let pending = match pending_logins.get(&token_name).await {
+        Err(error) => return error.into(),
+        Ok(Some(pending)) => pending,
+        Ok(None) => {
+            limiter.hit(None).await;
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: format!(
+                    "invalid two-factor token '{}'",
+                    second_factor.two_factor_token
+                ),
+            };
+        }
+    };
+
+    let account_id = pending.account_id.to_string();
+    if let Err(refusal) = limiter.check(&account_id).await {
+        return refusal.into();
+    }
+
+    if !two_factor::verify(
+        &mut db,
+        &from_serde_to_sqlx(&pending.account_id),
+        &second_factor.code,
+    )
+    .await
+    {
+        limiter.hit(Some(&account_id)).await;
+        return ApiResult::Failure {
             status: Status::BadRequest,
-            message,
+            message: String::from("invalid two-factor code"),
+        };
+    }
+
+    if let Err(error) = pending_logins.del(&token_name).await {
+        return error.into();
+    }
+    limiter.reset(&account_id).await;
+    if let Err(error) = open_session(
+        pending.account_id,
+        &pending.username,
+        pending.remember_me,
+        &device,
+        cookies,
+        sessions,
+    )
+    .await
+    {
+        return error.into();
+    }
+    ApiResult::Success {
+        status: Status::Ok,
+        payload: DefaultResponse {
+            response: String::from("great authentication success!"),
         },
     }
 }
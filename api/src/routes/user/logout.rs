@@ -3,21 +3,23 @@ use crate::cache::Cache;
 use crate::payload::DefaultResponse;
 use crate::result::ApiResult;
 use rocket::{
-    http::{Cookie, CookieJar, Status},
+    http::{CookieJar, Status},
     State,
 };
 
 /// Log out of the application.
 #[post("/logout")]
-pub fn post(
+pub async fn post(
     cookies: &CookieJar<'_>,
     is_connected: session::IsConnected,
-    sessions: &State<Cache<session::Connected>>,
+    sessions: &State<Cache<session::Active>>,
 ) -> ApiResult<DefaultResponse> {
     if let Some(sess) = is_connected.0 {
-        sessions.del(&sess.account_id.to_string());
+        if let Err(error) = sessions.del(&sess.key()).await {
+            return error.into();
+        }
     }
-    cookies.remove(Cookie::named("session"));
+    session::remove_cookies(cookies);
     ApiResult::Success {
         status: Status::Ok,
         payload: DefaultResponse {
@@ -0,0 +1,70 @@
+use crate::auth::session;
+use crate::payload::{DefaultResponse, Notifications};
+use crate::query::{self, PostgresDb};
+use crate::result::ApiResult;
+use crate::uuid::from_serde_to_sqlx;
+use rocket::http::Status;
+use rocket::serde::{json::Json, uuid::Uuid, Deserialize};
+use rocket_db_pools::Connection;
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NotificationIds {
+    notification_ids: Option<Vec<Uuid>>,
+}
+
+/// Get the notifications of the connected user, most recent first, with the
+/// number of unread ones.
+#[get("/notifications?<index>&<count>")]
+pub async fn get(
+    index: Option<u32>,
+    count: u32,
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> Option<Json<Notifications>> {
+    if count == 0 {
+        return None;
+    }
+
+    let account_id = from_serde_to_sqlx(&sess.account_id);
+    let notifications =
+        query::notifications(&mut db, &account_id, index.unwrap_or(0), count)
+            .await;
+    let unread_count = query::unread_notifications(&mut db, &account_id).await;
+    Some(Json(Notifications {
+        unread_count,
+        notifications,
+    }))
+}
+
+/// Mark the given notifications of the connected user as read. Every
+/// notification is marked as read when no ids are given.
+#[put("/notifications/read", data = "<read>", format = "json")]
+pub async fn put_read(
+    read: Json<NotificationIds>,
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+) -> ApiResult<DefaultResponse> {
+    let notification_ids = read
+        .into_inner()
+        .notification_ids
+        .map(|ids| ids.iter().map(from_serde_to_sqlx).collect::<Vec<_>>());
+    match query::read_notifications(
+        &mut db,
+        &from_serde_to_sqlx(&sess.account_id),
+        notification_ids.as_deref(),
+    )
+    .await
+    {
+        Ok(count) => ApiResult::Success {
+            status: Status::Ok,
+            payload: DefaultResponse {
+                response: format!("{} notifications marked as read", count),
+            },
+        },
+        Err(error) => ApiResult::Failure {
+            status: query::error_status(&error),
+            message: String::from("failed to mark notifications as read"),
+        },
+    }
+}
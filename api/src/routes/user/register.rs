@@ -6,6 +6,7 @@ use crate::{
     mail::Mailer,
     payload::{DefaultResponse, NewUser, Token},
     query::{self, PostgresDb},
+    rate_limit::Limiter,
     validation,
 };
 use rocket::serde::json::Json;
@@ -21,6 +22,7 @@ const REGISTRATION_TOKEN_LIFETIME: u64 = 300; // 5 minutes
 pub async fn post(
     new_user: Json<NewUser>,
     _sess: session::Unconnected,
+    limiter: Limiter<'_>,
     mut db: Connection<PostgresDb>,
     new_users: &State<Cache<NewUser>>,
     mailer: &State<Mailer>,
@@ -62,13 +64,23 @@ pub async fn post(
         };
     }
 
+    if let Err(refusal) = limiter.check(&user.email).await {
+        return refusal.into();
+    }
+    limiter.hit(Some(&user.email)).await;
+
     let token = Token::new();
     let token_name = format!("registration_token:{}", token);
-    new_users.set(
-        &token_name,
-        &user,
-        Duration::from_secs(REGISTRATION_TOKEN_LIFETIME),
-    );
+    if let Err(error) = new_users
+        .set(
+            &token_name,
+            &user,
+            Duration::from_secs(REGISTRATION_TOKEN_LIFETIME),
+        )
+        .await
+    {
+        return error.into();
+    }
 
     let link = format!(
         "{}/confirm.html?token={}",
@@ -3,6 +3,7 @@ use crate::config;
 use crate::mail::Mailer;
 use crate::payload::{DefaultResponse, Email, Token};
 use crate::query::{get_user_by_email, put_user, PostgresDb};
+use crate::rate_limit::Limiter;
 use crate::result::ApiResult;
 use crate::uuid::{from_serde_to_sqlx, from_sqlx_to_serde};
 use crate::validation;
@@ -28,7 +29,8 @@ pub struct PasswordReset {
 }
 
 /// Reset request containing the account id stored in the cache
-#[derive(Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub struct Request {
     account_id: Uuid,
 }
@@ -39,22 +41,33 @@ const RESET_TOKEN_LIFETIME: u64 = 300; // 5 minutes
 #[post("/reset", data = "<email>", format = "json")]
 pub async fn post(
     email: Json<Email>,
+    limiter: Limiter<'_>,
     mut db: Connection<PostgresDb>,
     reset_requests: &State<Cache<Request>>,
     mailer: &State<Mailer>,
 ) -> ApiResult<DefaultResponse> {
     let email = email.into_inner().email;
+    if let Err(refusal) = limiter.check(&email).await {
+        return refusal.into();
+    }
+    limiter.hit(Some(&email)).await;
+
     if let Some(account) = get_user_by_email(&email, &mut db).await {
         let token = Token::new();
         let token_name = format!("reset_token:{}", token);
         let request = Request {
             account_id: from_sqlx_to_serde(&account.account_id),
         };
-        reset_requests.set(
-            &token_name,
-            &request,
-            Duration::from_secs(RESET_TOKEN_LIFETIME),
-        );
+        if let Err(error) = reset_requests
+            .set(
+                &token_name,
+                &request,
+                Duration::from_secs(RESET_TOKEN_LIFETIME),
+            )
+            .await
+        {
+            return error.into();
+        }
         let link = format!(
             "{}/reset.html?token={}",
             config::FRONT_LINK.as_str(),
@@ -74,6 +87,7 @@ pub async fn post(
 #[put("/reset", data = "<password_reset>", format = "json")]
 pub async fn put(
     password_reset: Json<PasswordReset>,
+    limiter: Limiter<'_>,
     mut db: Connection<PostgresDb>,
     reset_requests: &State<Cache<Request>>,
 ) -> ApiResult<DefaultResponse> {
@@ -87,9 +101,11 @@ pub async fn put(
     }
 
     let token_name = format!("reset_token:{}", password_reset.reset_token);
-    let request = match reset_requests.del(&token_name) {
-        Some(item) => item,
-        None => {
+    let request = match reset_requests.del(&token_name).await {
+        Err(error) => return error.into(),
+        Ok(Some(item)) => item,
+        Ok(None) => {
+            limiter.hit(None).await;
             return ApiResult::Failure {
                 status: Status::BadRequest,
                 message: format!(
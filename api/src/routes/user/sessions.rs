@@ -0,0 +1,104 @@
+use crate::auth::session;
+use crate::cache::{self, Cache};
+use crate::payload::{DefaultResponse, Session};
+use crate::result::ApiResult;
+use rocket::http::{CookieJar, Status};
+use rocket::serde::uuid::Uuid;
+use rocket::State;
+use std::cmp::Reverse;
+
+/// Revoke every session of the given user.
+pub async fn revoke_all(
+    sess: &session::Connected,
+    sessions: &Cache<session::Active>,
+) -> Result<(), cache::Error> {
+    let prefix = session::Connected::account_prefix(&sess.account_id);
+    for active in sessions.list(&prefix).await? {
+        sessions.del(&active.connected.key()).await?;
+    }
+    Ok(())
+}
+
+/// List the active sessions of the connected user, most recent first.
+#[get("/sessions")]
+pub async fn get(
+    sess: session::Connected,
+    sessions: &State<Cache<session::Active>>,
+) -> ApiResult<Vec<Session>> {
+    let prefix = session::Connected::account_prefix(&sess.account_id);
+    let active_sessions = match sessions.list(&prefix).await {
+        Ok(active_sessions) => active_sessions,
+        Err(error) => return error.into(),
+    };
+    let mut active_sessions: Vec<Session> = active_sessions
+        .into_iter()
+        .map(|active| Session {
+            session_id: active.connected.session_id,
+            creation_ts: active.creation_ts,
+            last_seen: active.last_seen,
+            user_agent: active.user_agent,
+            ip: active.ip,
+            current: active.connected.session_id == sess.session_id,
+        })
+        .collect();
+    active_sessions.sort_by_key(|session| Reverse(session.last_seen));
+    ApiResult::Success {
+        status: Status::Ok,
+        payload: active_sessions,
+    }
+}
+
+/// Revoke one of the connected user's sessions.
+#[delete("/sessions/<session_id>")]
+pub async fn delete(
+    session_id: Uuid,
+    sess: session::Connected,
+    sessions: &State<Cache<session::Active>>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<DefaultResponse> {
+    let revoked = session::Connected {
+        session_id,
+        ..sess.clone()
+    };
+    match sessions.del(&revoked.key()).await {
+        Err(error) => return error.into(),
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: format!(
+                    "could not find '{}' session for current user",
+                    session_id
+                ),
+            };
+        }
+    }
+    if session_id == sess.session_id {
+        session::remove_cookies(cookies);
+    }
+    ApiResult::Success {
+        status: Status::Ok,
+        payload: DefaultResponse {
+            response: format!("session '{}' successfully revoked", session_id),
+        },
+    }
+}
+
+/// Log out everywhere by revoking every session of the connected user.
+#[delete("/sessions")]
+pub async fn delete_all(
+    sess: session::Connected,
+    sessions: &State<Cache<session::Active>>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<DefaultResponse> {
+    if let Err(error) = revoke_all(&sess, sessions).await {
+        return error.into();
+    }
+    session::remove_cookies(cookies);
+    ApiResult::Success {
+        status: Status::Ok,
+        payload: DefaultResponse {
+            response: String::from("logged out of every session"),
+        },
+    }
+}
@@ -0,0 +1,228 @@
+use crate::auth::{password, session, totp};
+use crate::cache::Cache;
+use crate::payload::{Code, DefaultResponse, RecoveryCodes, TwoFactorSecret};
+use crate::query::{self, PostgresDb};
+use crate::rate_limit::Limiter;
+use crate::result::ApiResult;
+use crate::uuid::{from_serde_to_sqlx, SqlxUuid};
+use rocket::http::Status;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::time::OffsetDateTime;
+use rocket::State;
+use rocket_db_pools::Connection;
+use std::time::Duration;
+
+/// Two-factor enrollment waiting for confirmation stored in the cache
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Enrollment {
+    secret: String,
+}
+
+// Time during which the enrollment can be confirmed in seconds.
+const ENROLLMENT_LIFETIME: u64 = 600; // 10 minutes
+
+// Issuer name displayed by the authenticator applications.
+const ISSUER: &str = "Pepecam";
+
+/// Current unix time.
+fn now() -> u64 {
+    OffsetDateTime::now_utc().unix_timestamp() as u64
+}
+
+/// Check a two-factor code or consume a recovery code of the user.
+pub async fn verify(
+    db: &mut Connection<PostgresDb>,
+    account_id: &SqlxUuid,
+    code: &str,
+) -> bool {
+    let secret = match query::get_user_by_account_id(account_id, db).await {
+        Some(account) => match account.totp_secret {
+            Some(secret) => secret,
+            None => return false,
+        },
+        None => return false,
+    };
+
+    // a code is only accepted once to prevent replays
+    if let Some(step) = totp::verify(&secret, code, now()) {
+        return query::use_totp_step(db, account_id, step)
+            .await
+            .unwrap_or(false);
+    }
+
+    let code = code.trim().to_lowercase();
+    for code_hash in query::recovery_codes(db, account_id).await {
+        if password::verify(&code, &code_hash).is_valid() {
+            return query::delete_recovery_code(db, account_id, &code_hash)
+                .await
+                .is_ok();
+        }
+    }
+    false
+}
+
+/// Start the two-factor enrollment of the connected user.
+#[post("/2fa")]
+pub async fn post(
+    sess: session::Connected,
+    mut db: Connection<PostgresDb>,
+    enrollments: &State<Cache<Enrollment>>,
+) -> ApiResult<TwoFactorSecret> {
+    let account_id = from_serde_to_sqlx(&sess.account_id);
+    match query::get_user_by_account_id(&account_id, &mut db).await {
+        Some(account) if account.totp_secret.is_none() => (),
+        Some(_) => {
+            return ApiResult::Failure {
+                status: Status::Conflict,
+                message: String::from(
+                    "two-factor authentication is already enabled",
+                ),
+            };
+        }
+        None => {
+            return ApiResult::Failure {
+                status: Status::InternalServerError,
+                message: String::from("could not find user account"),
+            };
+        }
+    }
+
+    let secret = totp::secret();
+    if let Err(error) = enrollments
+        .set(
+            &format!("totp_enrollment:{}", sess.account_id),
+            &Enrollment {
+                secret: secret.clone(),
+            },
+            Duration::from_secs(ENROLLMENT_LIFETIME),
+        )
+        .await
+    {
+        return error.into();
+    }
+    ApiResult::Success {
+        status: Status::Created,
+        payload: TwoFactorSecret {
+            uri: totp::uri(&secret, ISSUER, &sess.username),
+            secret,
+        },
+    }
+}
+
+/// Confirm the two-factor enrollment with a first code. This returns the
+/// recovery codes, they will not be shown again.
+#[put("/2fa", data = "<code>", format = "json")]
+pub async fn put(
+    code: Json<Code>,
+    sess: session::Connected,
+    limiter: Limiter<'_>,
+    mut db: Connection<PostgresDb>,
+    enrollments: &State<Cache<Enrollment>>,
+) -> ApiResult<RecoveryCodes> {
+    let code = code.into_inner().code;
+    let account_id = sess.account_id.to_string();
+    if let Err(refusal) = limiter.check(&account_id).await {
+        return refusal.into();
+    }
+
+    let token_name = format!("totp_enrollment:{}", sess.account_id);
+    let enrollment = match enrollments.get(&token_name).await {
+        Err(error) => return error.into(),
+        Ok(Some(enrollment)) => enrollment,
+        Ok(None) => {
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: String::from("no pending two-factor enrollment"),
+            };
+        }
+    };
+
+    let step = match totp::verify(&enrollment.secret, &code, now()) {
+        Some(step) => step,
+        None => {
+            limiter.hit(Some(&account_id)).await;
+            return ApiResult::Failure {
+                status: Status::BadRequest,
+                message: String::from("invalid two-factor code"),
+            };
+        }
+    };
+
+    let recovery_codes = totp::recovery_codes();
+    let mut code_hashes = Vec::new();
+    for recovery_code in recovery_codes.iter() {
+        match password::hash(recovery_code) {
+            Ok(code_hash) => code_hashes.push(code_hash),
+            Err(_) => {
+                return ApiResult::Failure {
+                    status: Status::InternalServerError,
+                    message: String::from("failed to create recovery codes"),
+                };
+            }
+        }
+    }
+
+    match query::set_two_factor(
+        &mut db,
+        &from_serde_to_sqlx(&sess.account_id),
+        Some((&enrollment.secret, step)),
+        &code_hashes,
+    )
+    .await
+    {
+        Ok(_) => {
+            // the enrollment expires anyway
+            if let Err(error) = enrollments.del(&token_name).await {
+                error!("failed to delete two-factor enrollment: {}", error);
+            }
+            ApiResult::Success {
+                status: Status::Ok,
+                payload: RecoveryCodes { recovery_codes },
+            }
+        }
+        Err(_) => ApiResult::Failure {
+            status: Status::InternalServerError,
+            message: String::from("failed to enable two-factor authentication"),
+        },
+    }
+}
+
+/// Disable two-factor authentication with a two-factor or recovery code.
+#[delete("/2fa", data = "<code>", format = "json")]
+pub async fn delete(
+    code: Json<Code>,
+    sess: session::Connected,
+    limiter: Limiter<'_>,
+    mut db: Connection<PostgresDb>,
+) -> ApiResult<DefaultResponse> {
+    let code = code.into_inner().code;
+    let account_id = sess.account_id.to_string();
+    if let Err(refusal) = limiter.check(&account_id).await {
+        return refusal.into();
+    }
+
+    let sqlx_account_id = from_serde_to_sqlx(&sess.account_id);
+    if !verify(&mut db, &sqlx_account_id, &code).await {
+        limiter.hit(Some(&account_id)).await;
+        return ApiResult::Failure {
+            status: Status::BadRequest,
+            message: String::from("invalid two-factor code"),
+        };
+    }
+
+    match query::set_two_factor(&mut db, &sqlx_account_id, None, &[]).await {
+        Ok(_) => ApiResult::Success {
+            status: Status::Ok,
+            payload: DefaultResponse {
+                response: String::from("two-factor authentication disabled"),
+            },
+        },
+        Err(_) => ApiResult::Failure {
+            status: Status::InternalServerError,
+            message: String::from(
+                "failed to disable two-factor authentication",
+            ),
+        },
+    }
+}
@@ -0,0 +1,13 @@
+use crate::payload::PublicProfile;
+use crate::query::{self, PostgresDb};
+use rocket::serde::json::Json;
+use rocket_db_pools::Connection;
+
+/// Get the public profile of a user.
+#[get("/<username>")]
+pub async fn get(
+    username: &str,
+    mut db: Connection<PostgresDb>,
+) -> Option<Json<PublicProfile>> {
+    query::public_profile(&mut db, username).await.map(Json)
+}
@@ -1,7 +1,9 @@
 //! Api parameters validation.
 
+use crate::config;
 use lazy_static::lazy_static;
 use regex::{Regex, RegexSet};
+use std::fs;
 
 // Username is simply a string of six to sixty-four word characters.
 const USERNAME_REGEX_STRING: &str = r"^\w{6,64}$";
@@ -27,6 +29,12 @@ const PASSWORD_REGEX_ERRORS: [&str; PASSWORD_REGEX_COUNT] = [
 const EMAIL_REGEX_STRING: &str =
     r"^[a-zA-Z0-9.!#$%&’*+/=?^_`{|}~-]+@[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)*$";
 
+// Superposable names and categories are short lowercase identifiers.
+const SUPERPOSABLE_REGEX_STRING: &str = r"^[a-z0-9_]{1,32}$";
+
+// Maximum length of a comment in characters, as in the 'comments' table.
+const COMMENT_MAX_LENGTH: usize = 512;
+
 lazy_static! {
     static ref USERNAME_REGEX: Regex =
         Regex::new(USERNAME_REGEX_STRING).expect("invalid username regex");
@@ -35,6 +43,48 @@ lazy_static! {
             .expect("invalid password regex set");
     static ref EMAIL_REGEX: Regex =
         Regex::new(EMAIL_REGEX_STRING).expect("invalid email regex");
+    static ref SUPERPOSABLE_REGEX: Regex =
+        Regex::new(SUPERPOSABLE_REGEX_STRING)
+            .expect("invalid superposable regex");
+    static ref BANNED_WORDS_REGEX: Option<Regex> = banned_words_regex();
+}
+
+/// Build a regex matching any of the words of the banned words file.
+fn banned_words_regex() -> Option<Regex> {
+    let words: Vec<String> = fs::read_to_string(&*config::BANNED_WORDS_FILE)
+        .expect("could not read BANNED_WORDS_FILE")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(whole_word)
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    let pattern = format!(r"(?i)(?:{})", words.join("|"));
+    Some(Regex::new(&pattern).expect("invalid banned words regex"))
+}
+
+/// Pattern matching the word only when it is not part of a longer one. A word
+/// ending with a symbol, like 'c++', must be followed by a non-word character
+/// instead of a word boundary.
+fn whole_word(word: &str) -> String {
+    let boundary = |c: Option<char>| match c {
+        Some(c) if c.is_alphanumeric() || c == '_' => r"\b",
+        _ => r"\B",
+    };
+    format!(
+        "{}{}{}",
+        boundary(word.chars().next()),
+        regex::escape(word),
+        boundary(word.chars().last())
+    )
+}
+
+/// Read the banned words file now instead of on the first comment so that a
+/// missing file prevents the api from starting.
+pub fn load_banned_words() {
+    lazy_static::initialize(&BANNED_WORDS_REGEX);
 }
 
 /// Check that the given username is a valid string
@@ -68,3 +118,101 @@ pub fn email(email: &str) -> Result<(), String> {
     }
     Ok(())
 }
+
+/// Check that the superposable name or category is a valid identifier
+pub fn superposable(name: &str) -> Result<(), String> {
+    if !SUPERPOSABLE_REGEX.is_match(name) {
+        return Err(String::from(
+            "superposable names and categories must be 1 to 32 lowercase \
+            letters, digits or underscores",
+        ));
+    }
+    Ok(())
+}
+
+/// Check that the comment is neither empty nor too long and that it does not
+/// contain any control character or banned word. Returns the trimmed comment.
+pub fn comment(comment: &str) -> Result<String, String> {
+    let comment = comment.trim();
+    if comment.is_empty() {
+        return Err(String::from("comment must not be empty"));
+    }
+    if comment.chars().count() > COMMENT_MAX_LENGTH {
+        return Err(format!(
+            "comment must be at most {} characters long",
+            COMMENT_MAX_LENGTH
+        ));
+    }
+    if comment.chars().any(|c| c.is_control() && c != '\n') {
+        return Err(String::from(
+            "comment must not contain control characters",
+        ));
+    }
+    if let Some(ref banned_words) = *BANNED_WORDS_REGEX {
+        if let Some(word) = banned_words.find(comment) {
+            return Err(format!(
+                "comment contains the banned word '{}'",
+                word.as_str()
+            ));
+        }
+    }
+    Ok(comment.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::sync::Once;
+
+    static BANNED_WORDS: Once = Once::new();
+
+    /// Write a banned words file before the first comment is checked.
+    fn configure() {
+        BANNED_WORDS.call_once(|| {
+            let file = env::temp_dir()
+                .join(format!("banned_words_{}.txt", std::process::id()));
+            fs::write(&file, "# one word per line\n\nfrog\n  c++ \n").unwrap();
+            env::set_var("BANNED_WORDS_FILE", &file);
+        });
+    }
+
+    #[test]
+    fn comment_is_trimmed() {
+        configure();
+        assert_eq!(comment("  feels good\n"), Ok(String::from("feels good")));
+        assert_eq!(comment("feels\ngood"), Ok(String::from("feels\ngood")));
+        assert!(comment(" \n\t ").is_err());
+    }
+
+    #[test]
+    fn comment_length() {
+        configure();
+        let longest = "é".repeat(COMMENT_MAX_LENGTH);
+        assert_eq!(comment(&format!(" {} ", longest)), Ok(longest.clone()));
+        assert!(comment(&format!("{}e", longest)).is_err());
+    }
+
+    #[test]
+    fn comment_control_characters() {
+        configure();
+        for invalid in ["feels\tgood", "feels\rgood", "feels\u{0}good"] {
+            assert!(comment(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn comment_banned_words() {
+        configure();
+        assert_eq!(
+            comment("what a FROG!"),
+            Err(String::from("comment contains the banned word 'FROG'"))
+        );
+        assert!(comment("frog").is_err());
+        assert!(comment("I code in c++ now").is_err());
+        // only whole words are banned
+        assert!(comment("frogs and leapfrog").is_ok());
+        assert!(comment("I code in abc++ now").is_ok());
+        assert!(comment("one word per line").is_ok());
+    }
+}
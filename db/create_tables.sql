@@ -5,34 +5,76 @@ CREATE TABLE IF NOT EXISTS accounts (
 	email VARCHAR(256) NOT NULL UNIQUE,
 	username VARCHAR(64) NOT NULL UNIQUE,
 	password_hash VARCHAR NOT NULL,
-	email_notifications BOOLEAN NOT NULL DEFAULT TRUE
+	email_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+	comment_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+	like_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+	mention_notifications BOOLEAN NOT NULL DEFAULT TRUE,
+	admin BOOLEAN NOT NULL DEFAULT FALSE,
+	totp_secret VARCHAR(64),
+	totp_last_step BIGINT,
+	creation_ts TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
-CREATE TYPE superposable AS ENUM (
-	'chic',
-	'cry',
-	'honk',
-	'rage',
-	'sad',
-	'smirk',
-	'stoned',
-	'sweat'
+CREATE TABLE IF NOT EXISTS superposables (
+	name VARCHAR(32) PRIMARY KEY,
+	file VARCHAR(64) NOT NULL,
+	category VARCHAR(32) NOT NULL DEFAULT 'pepe',
+	x INTEGER NOT NULL DEFAULT 0,
+	y INTEGER,
+	scale FLOAT8 NOT NULL DEFAULT 1,
+	rotation FLOAT8 NOT NULL DEFAULT 0,
+	enabled BOOLEAN NOT NULL DEFAULT TRUE,
+	creation_ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	CHECK (name ~ '^[a-z0-9_]{1,32}$'),
+	CHECK (x >= 0 AND (y IS NULL OR y >= 0))
 );
 
+INSERT INTO superposables (name, file) VALUES
+	('chic', 'chic.png'),
+	('cry', 'cry.png'),
+	('honk', 'honk.png'),
+	('rage', 'rage.png'),
+	('sad', 'sad.png'),
+	('smirk', 'smirk.png'),
+	('stoned', 'stoned.png'),
+	('sweat', 'sweat.png');
+
 CREATE TABLE IF NOT EXISTS pictures (
 	picture_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
 	account_id UUID NOT NULL,
-	superposable superposable NOT NULL,
 	creation_ts TIMESTAMPTZ NOT NULL DEFAULT NOW()
 );
 
 ALTER TABLE pictures
-	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id);
+	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
+
+CREATE TABLE IF NOT EXISTS picture_superposables (
+	picture_id UUID NOT NULL,
+	layer SMALLINT NOT NULL,
+	superposable VARCHAR(32) NOT NULL,
+	x INTEGER NOT NULL,
+	y INTEGER NOT NULL,
+	scale FLOAT8 NOT NULL DEFAULT 1,
+	rotation FLOAT8 NOT NULL DEFAULT 0,
+	PRIMARY KEY (picture_id, layer)
+);
+
+CREATE INDEX picture_superposables_superposable_idx
+	ON picture_superposables (superposable);
+
+ALTER TABLE picture_superposables
+	ADD FOREIGN KEY (picture_id) REFERENCES pictures (picture_id)
+	ON DELETE CASCADE;
+ALTER TABLE picture_superposables
+	ADD FOREIGN KEY (superposable) REFERENCES superposables (name)
+	ON UPDATE CASCADE;
 
 CREATE TABLE IF NOT EXISTS likes (
 	picture_id UUID NOT NULL,
 	account_id UUID NOT NULL,
 	value BOOLEAN NOT NULL DEFAULT TRUE,
+	creation_ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
 	CONSTRAINT NO_DUPLICATE_LIKE UNIQUE (picture_id, account_id)
 );
 
@@ -40,12 +82,16 @@ ALTER TABLE likes
 	ADD FOREIGN KEY (picture_id) REFERENCES pictures (picture_id)
 	ON DELETE CASCADE;
 ALTER TABLE likes
-	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id);
+	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
 
 CREATE TABLE IF NOT EXISTS comments (
+	comment_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+	parent_comment_id UUID,
 	picture_id UUID NOT NULL,
 	account_id UUID NOT NULL,
 	creation_ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	edited_ts TIMESTAMPTZ,
 	content VARCHAR(512) NOT NULL
 );
 
@@ -53,4 +99,76 @@ ALTER TABLE comments
 	ADD FOREIGN KEY (picture_id) REFERENCES pictures (picture_id)
 	ON DELETE CASCADE;
 ALTER TABLE comments
-	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id);
+	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
+ALTER TABLE comments
+	ADD FOREIGN KEY (parent_comment_id) REFERENCES comments (comment_id)
+	ON DELETE CASCADE;
+
+CREATE TABLE IF NOT EXISTS follows (
+	follower_id UUID NOT NULL,
+	followed_id UUID NOT NULL,
+	creation_ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	CONSTRAINT NO_DUPLICATE_FOLLOW UNIQUE (follower_id, followed_id),
+	CONSTRAINT NO_SELF_FOLLOW CHECK (follower_id <> followed_id)
+);
+
+ALTER TABLE follows
+	ADD FOREIGN KEY (follower_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
+ALTER TABLE follows
+	ADD FOREIGN KEY (followed_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
+
+CREATE TYPE notification_kind AS ENUM (
+	'comment',
+	'like',
+	'mention'
+);
+
+CREATE TABLE IF NOT EXISTS notifications (
+	notification_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+	account_id UUID NOT NULL,
+	author_id UUID NOT NULL,
+	picture_id UUID NOT NULL,
+	kind notification_kind NOT NULL,
+	creation_ts TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	read BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX notifications_account_idx
+	ON notifications (account_id, creation_ts DESC);
+
+-- a picture like is only notified once per user
+CREATE UNIQUE INDEX notifications_like_idx
+	ON notifications (account_id, author_id, picture_id)
+	WHERE kind = 'like';
+
+ALTER TABLE notifications
+	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
+ALTER TABLE notifications
+	ADD FOREIGN KEY (author_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
+ALTER TABLE notifications
+	ADD FOREIGN KEY (picture_id) REFERENCES pictures (picture_id)
+	ON DELETE CASCADE;
+
+CREATE TABLE IF NOT EXISTS recovery_codes (
+	account_id UUID NOT NULL,
+	code_hash VARCHAR NOT NULL
+);
+
+ALTER TABLE recovery_codes
+	ADD FOREIGN KEY (account_id) REFERENCES accounts (account_id)
+	ON DELETE CASCADE;
+
+CREATE TABLE IF NOT EXISTS cache (
+	namespace VARCHAR(64) NOT NULL,
+	key VARCHAR(256) NOT NULL,
+	value VARCHAR NOT NULL,
+	expiration_ts TIMESTAMPTZ NOT NULL,
+	PRIMARY KEY (namespace, key)
+);
+
+CREATE INDEX cache_expiration_idx ON cache (namespace, expiration_ts);
@@ -61,14 +61,14 @@ $$;
 
 -- Random Superposable function
 CREATE FUNCTION random_superposable()
-	RETURNS superposable
+	RETURNS VARCHAR
 	LANGUAGE PLPGSQL
 AS
 $$
-	DECLARE super superposable;
+	DECLARE super VARCHAR;
 	BEGIN
-		SELECT superposable INTO super
-			FROM unnest(enum_range(NULL::superposable)) superposable
+		SELECT name INTO super FROM superposables
+		WHERE enabled = TRUE
 		ORDER BY random() LIMIT 1;
 		RETURN super;
 	END
@@ -79,10 +79,19 @@ INSERT INTO pictures
 SELECT
 	uuid_generate_v4(),
 	random_user(),
-	random_superposable(),
 	random_date(CURRENT_SETTING('test.date_min'), CURRENT_SETTING('test.date_max'))
 FROM GENERATE_SERIES(1, CURRENT_SETTING('test.n_pictures')::int);
 
+-- Generate Picture Superposables
+INSERT INTO picture_superposables (picture_id, layer, superposable, x, y)
+SELECT
+	picture_id,
+	0,
+	random_superposable(),
+	0,
+	0
+FROM pictures;
+
 -- Generate Likes
 INSERT INTO likes
 SELECT
@@ -93,7 +102,7 @@ FROM GENERATE_SERIES(1, CURRENT_SETTING('test.n_likes')::int)
 ON CONFLICT DO NOTHING;
 
 -- Generate Comments
-INSERT INTO comments
+INSERT INTO comments (picture_id, account_id, creation_ts, content)
 SELECT
 	random_picture(),
 	random_user(),
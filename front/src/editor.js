@@ -54,7 +54,7 @@ const initEditor = async () => {
 
   // Set username for gallery
   const gallery = document.querySelector('pepe-gallery')
-  const { username } = getCookie('session')
+  const { username } = getCookie('user')
   gallery.setAttribute('data-username', username)
   gallery.removeAttribute('disabled')
 
@@ -25,6 +25,25 @@ PepeGalleryTemplate.innerHTML = `
         Username
         <input type="text" name="username" placeholder="username">
       </label>
+      <label class="form-field">
+        Sort
+        <select name="sort">
+          <option value="newest">newest</option>
+          <option value="oldest">oldest</option>
+          <option value="most_liked">most liked</option>
+          <option value="most_commented">most commented</option>
+          <option value="hot">hot</option>
+          <option value="controversial">controversial</option>
+        </select>
+      </label>
+      <label class="form-field">
+        Period
+        <select name="window">
+          <option value="all">all time</option>
+          <option value="week">last week</option>
+          <option value="day">last day</option>
+        </select>
+      </label>
       <fieldset id="superposables-fieldset" class="form-field">
         <legend>Superposables</legend>
       </fieldset>
@@ -59,6 +78,7 @@ class PepeGallery extends HTMLElement {
   constructor() {
     super()
     this._index = -1
+    this._cursor = null
     this._finished = false
     this._superposables = []
     this.attachShadow({ mode: 'open' })
@@ -120,6 +140,23 @@ class PepeGallery extends HTMLElement {
       }
     })
     if (!this.disabled) this._getPepePosts()
+
+    // Update like counts in real time
+    this._events = new EventSource(`${info.api}/events`)
+    this._events.addEventListener('likes', this._onLikes.bind(this))
+  }
+
+  disconnectedCallback() {
+    this._events.close()
+  }
+
+  _onLikes(event) {
+    const { picture_id, like_count, dislike_count } = JSON.parse(event.data)
+    const selector = `[data-picture-id="${picture_id}"]`
+    this.shadowRoot.querySelectorAll(selector).forEach(element => {
+      element.setAttribute('data-like-count', like_count)
+      element.setAttribute('data-dislike-count', dislike_count)
+    })
   }
 
   attributeChangedCallback(name, oldValue, newValue) {
@@ -175,6 +212,7 @@ class PepeGallery extends HTMLElement {
 
   _reset() {
     this._index = -1
+    this._cursor = null
     this._finished = false
     const selector = this.thumbnail ? 'pepe-thumbnail' : 'pepe-post'
     this.shadowRoot.querySelectorAll(selector).forEach(element => {
@@ -192,7 +230,7 @@ class PepeGallery extends HTMLElement {
     const {
       picture_id,
       account_id,
-      superposable,
+      superposables,
       creation_ts,
       author,
       like_count,
@@ -205,7 +243,7 @@ class PepeGallery extends HTMLElement {
     const attributes = {
       'data-picture-id': picture_id,
       'data-account-id': account_id,
-      'data-superposable': superposable,
+      'data-superposables': superposables.join(','),
       'data-creation-ts': creation_ts,
       'data-author': author,
       'data-like-count': like_count,
@@ -235,7 +273,8 @@ class PepeGallery extends HTMLElement {
   async _getPepePosts() {
     try {
       this._index += 1
-      let url = `${info.api}/pictures?index=${this._index}&count=${this.count}`
+      let url = `${info.api}/pictures?count=${this.count}`
+      if (this._cursor) url += `&after=${this._cursor}`
       if (this.username) url += `&username=${this.username}`
       if (this.filters) url += this.filters
       const response = await fetch(url, { method: 'GET', credentials: 'include' })
@@ -248,6 +287,7 @@ class PepeGallery extends HTMLElement {
       }
 
       for (const post of posts) this.appendPicture(post)
+      this._cursor = posts[posts.length - 1].cursor
       if (this._index === 0) this._ready()
     } catch (error) {
       this._finished = true
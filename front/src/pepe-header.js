@@ -19,7 +19,12 @@ async function dialogSubmit() {
     const data = await response.json()
     if (!response.ok) {
       throw new ApiError(data)
-    } else if (id === 'login-dialog') {
+    } else if (id === 'login-dialog' && data.two_factor_token) {
+      const twoFactorDialog = this.parentNode.querySelector('#two-factor-dialog')
+      twoFactorDialog.querySelector('input[name="two_factor_token"]').value =
+        data.two_factor_token
+      twoFactorDialog.showModal()
+    } else if (id === 'login-dialog' || id === 'two-factor-dialog') {
       submit.dispatchEvent(toggleConnectedEvent())
     } else if (id === 'signup-dialog' || id === 'reset-dialog') {
       asyncAlert(`Success: ${data.response}`)
@@ -52,13 +57,22 @@ PepeHeaderTemplate.innerHTML = `
     <h3>Login</h3>
     <form id="login-form" method="dialog" class="form">
       <label class="form-field">
-        username
-        <input type="text" name="username" placeholder="username" required>
+        username or email
+        <input
+          type="text"
+          name="identifier"
+          placeholder="username or email"
+          required
+        >
       </label>
       <label class="form-field">
         password
         <input type="password" name="password" placeholder="password" required>
       </label>
+      <label class="form-field">
+        remember me
+        <input type="checkbox" name="remember_me">
+      </label>
       <div class="form-field" id="forgot-password-container">
         <a href="#" id="forgot-password">forgot password?</a>
       </div>
@@ -69,6 +83,27 @@ PepeHeaderTemplate.innerHTML = `
     </form>
   </dialog>
 
+  <dialog id="two-factor-dialog">
+    <h3>Two-Factor Authentication</h3>
+    <form id="two-factor-form" method="dialog" class="form">
+      <input type="hidden" name="two_factor_token">
+      <label class="form-field">
+        code
+        <input
+          type="text"
+          name="code"
+          placeholder="authenticator or recovery code"
+          autocomplete="one-time-code"
+          required
+        >
+      </label>
+      <div class="form-field">
+        <button type="submit">verify</button>
+        <button type="reset">cancel</button>
+      </div>
+    </form>
+  </dialog>
+
   <dialog id="reset-dialog">
     <h3>Reset Password</h3>
     <form id="reset-form" method="dialog" class="form">
@@ -127,6 +162,11 @@ class PepeHeader extends HTMLElement {
       'url',
       `${info.api}/user/login`
     )
+    const twoFactorDialog = this.shadowRoot.querySelector('#two-factor-dialog')
+    twoFactorDialog.setAttribute(
+      'url',
+      `${info.api}/user/login/2fa`
+    )
     const resetDialog = this.shadowRoot.querySelector('#reset-dialog')
     resetDialog.setAttribute(
       'url',
@@ -173,6 +213,12 @@ class PepeHeader extends HTMLElement {
     loginDialog
       .querySelector('button[type="reset"]')
       .addEventListener('click', () => loginDialog.close())
+    twoFactorDialog
+      .querySelector('button[type="submit"]')
+      .addEventListener('click', dialogSubmit.bind(twoFactorDialog))
+    twoFactorDialog
+      .querySelector('button[type="reset"]')
+      .addEventListener('click', () => twoFactorDialog.close())
     resetDialog
       .querySelector('button[type="submit"]')
       .addEventListener('click', dialogSubmit.bind(resetDialog))
@@ -190,7 +236,7 @@ class PepeHeader extends HTMLElement {
       'toggle-connected',
       this._onToggleConnected.bind(this)
     )
-    this._onToggleConnected({ detail: { connected: !!getCookie('session') } })
+    this._onToggleConnected({ detail: { connected: !!getCookie('user') } })
   }
 
   _onToggleConnected({ detail: { connected } }) {
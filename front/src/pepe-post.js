@@ -244,9 +244,10 @@ class PepePost extends HTMLElement {
     return this.shadowRoot.querySelector('#post-picture').src
   }
 
-  createComment(feed, { author, content }) {
+  createComment(feed, { author, content, depth }) {
     const commentElement = document.createElement('div')
     commentElement.classList.add('comment')
+    if (depth) commentElement.style.marginLeft = `${depth}em`
     commentElement.textContent = `@${author}: ${content}`
     feed.append(commentElement)
   }
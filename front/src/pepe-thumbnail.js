@@ -31,7 +31,7 @@ class PepeThumbnail extends HTMLElement {
     const detail = {
       'data-picture-id': this.getAttribute('data-picture-id'),
       'data-account-id': this.getAttribute('data-account-id'),
-      'data-superposable': this.getAttribute('data-superposable'),
+      'data-superposables': this.getAttribute('data-superposables'),
       'data-creation-ts': this.getAttribute('data-creation-ts'),
       'data-author': this.getAttribute('data-author'),
       'data-like-count': this.getAttribute('data-like-count'),
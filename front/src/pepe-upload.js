@@ -20,7 +20,7 @@ PepeUploadTemplate.innerHTML = `
         Import Picture
         <input
           type="file"
-          accept="image/jpeg,image/png"
+          accept="image/jpeg,image/png,image/webp,image/heic,image/heif,.heic,.heif"
           capture="environment"
           disabled
         >
@@ -1,4 +1,10 @@
-import { info, forbidUnconnected, submitForm, ApiError } from './utils.js'
+import {
+  info,
+  forbidUnconnected,
+  submitForm,
+  toggleConnectedEvent,
+  ApiError,
+} from './utils.js'
 
 const form = document.querySelector('#profile-form')
 const passwordField = form.querySelector('input[name="password"]')
@@ -6,6 +12,8 @@ const confirmPasswordField = form.querySelector(
   'input[name="password-confirm"]',
 )
 const profileSubmitButton = form.querySelector('#profile-submit-button')
+const deleteForm = document.querySelector('#delete-form')
+const deleteSubmitButton = deleteForm.querySelector('#delete-submit-button')
 
 const validatePassword = () => {
   if (passwordField.value !== confirmPasswordField.value) {
@@ -17,6 +25,13 @@ const validatePassword = () => {
 
 let user
 
+const notificationSettings = [
+  'email_notifications',
+  'comment_notifications',
+  'like_notifications',
+  'mention_notifications',
+]
+
 const profileSubmit = async (event) => {
   event.preventDefault()
   const url = `${info.api}/user`
@@ -24,12 +39,14 @@ const profileSubmit = async (event) => {
   try {
     if (!user || form.reportValidity() === false) return
     const formData = new FormData(form)
-    const notifications = formData.get('email_notifications') === 'on'
-    if (notifications === user.email_notifications) {
-      formData.delete('email_notifications')
-    } else if (!notifications) {
-      formData.set('email_notifications', 'off')
-    }
+    notificationSettings.forEach((setting) => {
+      const notifications = formData.get(setting) === 'on'
+      if (notifications === user[setting]) {
+        formData.delete(setting)
+      } else if (!notifications) {
+        formData.set(setting, 'off')
+      }
+    })
     const response = await submitForm(formData, 'PUT', url)
     if (!response.ok) {
       const error = await response.json()
@@ -42,6 +59,25 @@ const profileSubmit = async (event) => {
   }
 }
 
+const deleteSubmit = async (event) => {
+  event.preventDefault()
+  const url = `${info.api}/user`
+
+  try {
+    if (deleteForm.reportValidity() === false) return
+    if (!confirm('Delete your account and all of your pictures?')) return
+    const response = await submitForm(new FormData(deleteForm), 'DELETE', url)
+    if (!response.ok) {
+      const error = await response.json()
+      throw new ApiError(error)
+    }
+    alert('Success: Account deleted')
+    deleteSubmitButton.dispatchEvent(toggleConnectedEvent())
+  } catch (error) {
+    alert(`${error.name}: ${error.message}`)
+  }
+}
+
 const getUser = async () => {
   const url = `${info.api}/user`
   const response = await fetch(url, { credentials: 'include' })
@@ -57,6 +93,10 @@ const initProfile = async () => {
   passwordField.addEventListener('change', validatePassword)
   confirmPasswordField.addEventListener('keyup', validatePassword)
   profileSubmitButton.addEventListener('click', profileSubmit)
+  deleteSubmitButton.addEventListener('click', deleteSubmit)
+  document
+    .querySelector('#export-link')
+    .setAttribute('href', `${info.api}/user/export`)
 
   // Check if user is connected
   forbidUnconnected()
@@ -65,14 +105,14 @@ const initProfile = async () => {
   // Set form values
   try {
     user = await getUser()
-    const { username, email, email_notifications } = user
+    const { username, email } = user
     form
       .querySelector('input[name="username"]')
       .setAttribute('placeholder', username)
     form.querySelector('input[name="email"]').setAttribute('placeholder', email)
-    form
-      .querySelector('input[name="email_notifications"]')
-      .checked = email_notifications
+    notificationSettings.forEach((setting) => {
+      form.querySelector(`input[name="${setting}"]`).checked = user[setting]
+    })
   } catch (error) {
     alert(`${error.name}: ${error.message}`)
   }
@@ -44,7 +44,7 @@ export const asyncAlert = (message, href) => {
 }
 
 export const forbidUnconnected = () => {
-  if (!getCookie('session')) {
+  if (!getCookie('user')) {
     asyncAlert('Error: You must be connected to access this page', '/')
     return true
   }
@@ -55,7 +55,7 @@ export const toggleConnectedEvent = () =>
   new CustomEvent('toggle-connected', {
     bubbles: true,
     composed: true,
-    detail: { connected: !!getCookie('session') },
+    detail: { connected: !!getCookie('user') },
   })
 
 export const createElement = (tag, attributes = {}) => {
@@ -69,7 +69,7 @@ export const createElement = (tag, attributes = {}) => {
 export const submitForm = (formData, method, url) => {
   const data = {}
   for (const [key, value] of formData.entries()) {
-    if (key === 'email_notifications') {
+    if (key.endsWith('_notifications') || key === 'remember_me') {
       data[key] = value === 'on'
     } else if (key !== 'password-confirm' && value) {
       data[key] = value
@@ -116,7 +116,7 @@ export const getSuperposables = async () => {
       throw new Error('no superposable found')
     }
 
-    return superposables
+    return superposables.map(({ name }) => name)
   } catch (error) {
     alert(`${error.name}: ${error.message}`)
   }